@@ -2,6 +2,7 @@ use std::{
     cell::RefCell,
     future::Future,
     io::Result,
+    rc::Rc,
     task::{Context, Poll},
 };
 
@@ -25,20 +26,45 @@ where
     })
 }
 
-pub fn spawn<F>(f: F)
+pub fn spawn<F>(f: F) -> JoinHandle
 where
     F: Future<Output = ()> + 'static,
 {
     EXECUTOR.with(|executor| {
         let executor = executor.borrow();
-        executor.spawn(f);
-    });
+        executor.spawn(f)
+    })
+}
+
+pub struct JoinHandle {
+    task: Rc<Task>,
+}
+
+impl JoinHandle {
+    pub fn is_finished(&self) -> bool {
+        matches!(self.task.future.try_borrow(), Ok(future) if future.is_none())
+    }
+
+    /// Drops the task's future, releasing whatever it holds (sockets,
+    /// timers...). A task can't cancel itself while it is being polled.
+    pub fn cancel(&self) {
+        if let Ok(mut future) = self.task.future.try_borrow_mut() {
+            future.take();
+            self.task.waker.borrow_mut().take();
+        }
+    }
 }
 
 pub struct Executor {
     pub tasks: RefCell<TaskQueue>,
 }
 
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Executor {
     pub fn new() -> Self {
         Executor {
@@ -46,13 +72,16 @@ impl Executor {
         }
     }
 
-    pub fn spawn<F>(&self, f: F)
+    pub fn spawn<F>(&self, f: F) -> JoinHandle
     where
         F: Future<Output = ()> + 'static,
     {
-        self.tasks.borrow_mut().push(Task {
-            future: RefCell::new(Box::pin(f)),
+        let task = Rc::new(Task {
+            future: RefCell::new(Some(Box::pin(f))),
+            waker: RefCell::new(None),
         });
+        self.tasks.borrow_mut().push(task.clone());
+        JoinHandle { task }
     }
 
     pub fn run(&self) -> Result<()> {
@@ -64,27 +93,46 @@ impl Executor {
                     let mut tasks = self.tasks.borrow_mut();
                     tasks.pop()
                 } {
-                    let waker = {
-                        let sender = self.tasks.borrow().sender();
-                        let waker_task = task.clone();
-                        waker_fn(move || {
-                            // executor schedule task again
-                            println!(
-                                "{} {:?} waking up to requeue future polling",
-                                format!("[{}]", std::process::id()).truecolor(0, 255, 136),
-                                std::thread::current().id(),
-                            );
-                            sender.send(waker_task.clone()).unwrap();
+                    // woken after it already finished or was cancelled
+                    if task.future.borrow().is_none() {
+                        continue;
+                    }
+
+                    // reuse the same waker across polls so the reactor can
+                    // recognize a task that is already waiting on a source
+                    let waker = task
+                        .waker
+                        .borrow_mut()
+                        .get_or_insert_with(|| {
+                            let sender = self.tasks.borrow().sender();
+                            let waker_task = task.clone();
+                            waker_fn(move || {
+                                // executor schedule task again
+                                println!(
+                                    "{} {:?} waking up to requeue future polling",
+                                    format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                                    std::thread::current().id(),
+                                );
+                                sender.send(waker_task.clone()).unwrap();
+                            })
                         })
-                    };
+                        .clone();
                     let mut context = Context::from_waker(&waker);
                     println!(
                         "{} {:?} received task, polling future...",
                         format!("[{}]", std::process::id()).truecolor(0, 255, 136),
                         std::thread::current().id(),
                     );
-                    match task.future.borrow_mut().as_mut().poll(&mut context) {
+                    let mut future = task.future.borrow_mut();
+                    let poll = match future.as_mut() {
+                        Some(future) => future.as_mut().poll(&mut context),
+                        None => continue,
+                    };
+                    match poll {
                         Poll::Ready(_) => {
+                            future.take();
+                            // the cached waker holds the task, drop it to free both
+                            task.waker.borrow_mut().take();
                             println!(
                                 "{} {:?} poll ready complete on spawned task",
                                 format!("[{}]", std::process::id()).truecolor(0, 255, 136),
@@ -95,6 +143,8 @@ impl Executor {
                     };
                 }
 
+                // tasks can also wake each other without any I/O involved
+                self.tasks.borrow_mut().receive();
                 if self.tasks.borrow().is_empty() {
                     break;
                 }
@@ -124,7 +174,11 @@ impl Executor {
             let mut events = Vec::new();
             {
                 let reactor = current.borrow();
-                reactor.wait(&mut events, None)?;
+                match reactor.wait(&mut events, reactor.next_timeout()) {
+                    // a signal landed mid-wait, its self-pipe shows up on the next wait
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => return Ok(0),
+                    result => result?,
+                };
             }
 
             let wakers = {
@@ -0,0 +1,65 @@
+use std::io::{ErrorKind, Read, Result, Write};
+use std::os::unix::io::AsRawFd;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::reactor::REACTOR;
+
+pub struct ReadFuture<'io, S: Read + AsRawFd> {
+    io: &'io mut S,
+    buf: &'io mut [u8],
+}
+
+impl<'io, S: Read + AsRawFd> ReadFuture<'io, S> {
+    pub fn new(io: &'io mut S, buf: &'io mut [u8]) -> Self {
+        ReadFuture { io, buf }
+    }
+}
+
+impl<S: Read + AsRawFd> Future for ReadFuture<'_, S> {
+    type Output = Result<usize>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        match state.io.read(state.buf) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                REACTOR.with(|current| {
+                    current.borrow_mut().wake_on_readable(&*state.io, cx);
+                });
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+pub struct WriteFuture<'io, S: Write + AsRawFd> {
+    io: &'io mut S,
+    buf: &'io [u8],
+}
+
+impl<'io, S: Write + AsRawFd> WriteFuture<'io, S> {
+    pub fn new(io: &'io mut S, buf: &'io [u8]) -> Self {
+        WriteFuture { io, buf }
+    }
+}
+
+impl<S: Write + AsRawFd> Future for WriteFuture<'_, S> {
+    type Output = Result<usize>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        match state.io.write(state.buf) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                REACTOR.with(|current| {
+                    current.borrow_mut().wake_on_writable(&*state.io, cx);
+                });
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
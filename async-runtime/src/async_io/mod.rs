@@ -1,4 +1,9 @@
 pub mod executor;
+pub mod io;
 pub mod reactor;
+pub mod select;
+pub mod shutdown;
+pub mod stream;
 pub mod task_queue;
+pub mod timer;
 pub mod waker_util;
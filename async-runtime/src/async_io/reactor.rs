@@ -1,9 +1,9 @@
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     io::Result,
     task::{Context, Waker},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use polling::{Event, Poller, Source};
@@ -15,14 +15,26 @@ thread_local! {
 pub struct Reactor {
     readable: HashMap<usize, Vec<Waker>>,
     writable: HashMap<usize, Vec<Waker>>,
+    registered: HashSet<usize>,
+    timers: BTreeMap<(Instant, usize), Waker>,
+    next_timer: usize,
     poller: Poller,
 }
 
+impl Default for Reactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Reactor {
     pub fn new() -> Self {
         Reactor {
             readable: HashMap::new(),
             writable: HashMap::new(),
+            registered: HashSet::new(),
+            timers: BTreeMap::new(),
+            next_timer: 0,
             poller: Poller::new().unwrap(),
         }
     }
@@ -40,40 +52,82 @@ impl Reactor {
 
     pub fn wake_on_readable(&mut self, source: impl Source, cx: &mut Context) {
         let key = source.raw() as usize;
-        self.readable
-            .entry(key)
-            .or_default()
-            .push(cx.waker().clone());
-        self.poller.modify(source, self.get_interest(key)).unwrap();
+        push_waker(self.readable.entry(key).or_default(), cx);
+        self.register(source, key);
     }
 
     pub fn wake_on_writable(&mut self, source: impl Source, cx: &mut Context) {
         let fd = source.raw();
 
         let key = fd as usize;
-        self.writable
-            .entry(key)
-            .or_default()
-            .push(cx.waker().clone());
+        push_waker(self.writable.entry(key).or_default(), cx);
+        self.register(source, key);
+    }
 
-        self.poller.modify(source, self.get_interest(key)).unwrap();
+    // sources are added to the poller the first time we wait on them
+    // and modified with the updated interest from then on
+    fn register(&mut self, source: impl Source, key: usize) {
+        let interest = self.get_interest(key);
+        if self.registered.insert(key) {
+            self.poller.add(source, interest).unwrap();
+        } else {
+            self.poller.modify(source, interest).unwrap();
+        }
     }
 
     pub fn remove(&mut self, source: impl Source) {
         let key = source.raw() as usize;
-        self.poller.delete(source).unwrap();
+        if self.registered.remove(&key) {
+            self.poller.delete(source).unwrap();
+        }
         self.readable.remove(&key);
         self.writable.remove(&key);
     }
 
-    pub fn add(&self, source: impl Source) {
+    // stop watching the source without removing it from the poller
+    pub fn clear_interest(&mut self, source: impl Source) {
         let key = source.raw() as usize;
-        self.poller.add(source, self.get_interest(key)).unwrap();
+        self.readable.remove(&key);
+        self.writable.remove(&key);
+        if self.registered.contains(&key) {
+            self.poller.modify(source, Event::none(key)).unwrap();
+        }
+    }
+
+    pub fn add(&mut self, source: impl Source) {
+        let key = source.raw() as usize;
+        self.register(source, key);
+    }
+
+    pub fn timer_id(&mut self) -> usize {
+        self.next_timer += 1;
+        self.next_timer
+    }
+
+    pub fn wake_at(&mut self, when: Instant, id: usize, cx: &mut Context) {
+        self.timers.insert((when, id), cx.waker().clone());
+    }
+
+    pub fn remove_timer(&mut self, when: Instant, id: usize) {
+        self.timers.remove(&(when, id));
+    }
+
+    // how long the poller can block before the nearest timer is due
+    pub fn next_timeout(&self) -> Option<Duration> {
+        self.timers
+            .keys()
+            .next()
+            .map(|(when, _)| when.saturating_duration_since(Instant::now()))
     }
 
     pub fn wakers(&mut self, events: Vec<Event>) -> Vec<Waker> {
         let mut wakers = Vec::new();
 
+        let pending = self.timers.split_off(&(Instant::now(), usize::MAX));
+        for (_, waker) in std::mem::replace(&mut self.timers, pending) {
+            wakers.push(waker);
+        }
+
         for ev in events {
             if let Some((_, readers)) = self.readable.remove_entry(&ev.key) {
                 for waker in readers {
@@ -95,6 +149,14 @@ impl Reactor {
     }
 
     pub fn waiting_on_events(&self) -> bool {
-        !self.readable.is_empty() || !self.writable.is_empty()
+        !self.readable.is_empty() || !self.writable.is_empty() || !self.timers.is_empty()
+    }
+}
+
+// a task re-polled while still waiting on the same source (e.g. racing it
+// against another future in a loop) shouldn't pile up duplicate wakers
+fn push_waker(wakers: &mut Vec<Waker>, cx: &mut Context) {
+    if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+        wakers.push(cx.waker().clone());
     }
 }
@@ -0,0 +1,244 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Result;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use colored::Colorize;
+
+use crate::async_signal::signal::{signal, SignalKind};
+
+use super::executor::{self, JoinHandle};
+use super::select::{select, Either};
+use super::timer::sleep;
+
+/// Coordinates a graceful shutdown: tasks spawned through it are tracked so
+/// that, once shutdown is triggered, we can wait for them to drain and
+/// cancel whatever is still running after a deadline.
+pub struct Shutdown {
+    inner: Rc<Inner>,
+}
+
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    inner: Rc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    triggered: Cell<bool>,
+    triggered_wakers: RefCell<Vec<Waker>>,
+    tasks: RefCell<HashMap<usize, JoinHandle>>,
+    finished_wakers: RefCell<Vec<Waker>>,
+    next_id: Cell<usize>,
+    watchers: RefCell<Vec<JoinHandle>>,
+}
+
+impl Inner {
+    fn trigger(&self) {
+        if !self.triggered.replace(true) {
+            for waker in self.triggered_wakers.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn finish(&self, id: usize) {
+        self.tasks.borrow_mut().remove(&id);
+        for waker in self.finished_wakers.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown {
+            inner: Rc::new(Inner::default()),
+        }
+    }
+
+    pub fn handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Triggers the shutdown whenever the process receives `kind`.
+    pub fn on_signal(&self, kind: SignalKind) -> Result<()> {
+        let mut signal = signal(kind)?;
+        let handle = self.handle();
+        let watcher = executor::spawn(async move {
+            if signal.recv().await.is_some() {
+                handle.shutdown();
+            }
+        });
+        self.inner.watchers.borrow_mut().push(watcher);
+        Ok(())
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.inner.triggered.get()
+    }
+
+    /// Resolves once shutdown has been requested.
+    pub fn triggered(&self) -> Triggered<'_> {
+        Triggered { inner: &self.inner }
+    }
+
+    /// Spawns a task that the shutdown will wait on (and cancel if needed).
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let id = self.inner.next_id.get();
+        self.inner.next_id.set(id + 1);
+
+        let guard = TaskGuard {
+            inner: self.inner.clone(),
+            id,
+        };
+        let handle = executor::spawn(async move {
+            let _guard = guard;
+            f.await;
+        });
+        self.inner.tasks.borrow_mut().insert(id, handle);
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.inner.tasks.borrow().len()
+    }
+
+    /// Resolves once fewer than `max` tracked tasks are in flight.
+    pub fn below(&self, max: usize) -> Below<'_> {
+        Below {
+            inner: &self.inner,
+            max,
+        }
+    }
+
+    /// Waits up to `deadline` for tracked tasks to finish, then cancels the
+    /// rest. Returns how many tasks had to be cancelled.
+    pub async fn drain(&self, deadline: Duration) -> usize {
+        println!(
+            "{} {:?} shutting down, draining {} connection(s)",
+            format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+            std::thread::current().id(),
+            self.in_flight()
+        );
+
+        // signal watchers would otherwise keep the executor waiting forever
+        for watcher in self.inner.watchers.take() {
+            watcher.cancel();
+        }
+
+        if let Either::Left(()) = select(self.below(1), sleep(deadline)).await {
+            return 0;
+        }
+
+        let remaining: Vec<JoinHandle> = self
+            .inner
+            .tasks
+            .borrow_mut()
+            .drain()
+            .map(|(_, t)| t)
+            .collect();
+        println!(
+            "{} {:?} shutdown deadline reached, cancelling {} connection(s)",
+            format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+            std::thread::current().id(),
+            remaining.len()
+        );
+        for task in remaining.iter() {
+            task.cancel();
+        }
+        remaining.len()
+    }
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.inner.trigger();
+    }
+}
+
+struct TaskGuard {
+    inner: Rc<Inner>,
+    id: usize,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.inner.finish(self.id);
+    }
+}
+
+pub struct Triggered<'shutdown> {
+    inner: &'shutdown Inner,
+}
+
+impl Future for Triggered<'_> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.inner.triggered.get() {
+            return Poll::Ready(());
+        }
+        push_waker(&self.inner.triggered_wakers, cx);
+        Poll::Pending
+    }
+}
+
+pub struct Below<'shutdown> {
+    inner: &'shutdown Inner,
+    max: usize,
+}
+
+impl Future for Below<'_> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.inner.tasks.borrow().len() < self.max {
+            return Poll::Ready(());
+        }
+        push_waker(&self.inner.finished_wakers, cx);
+        Poll::Pending
+    }
+}
+
+fn push_waker(wakers: &RefCell<Vec<Waker>>, cx: &mut Context) {
+    let mut wakers = wakers.borrow_mut();
+    if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+        wakers.push(cx.waker().clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_drain_cancels_after_deadline() {
+        executor::block_on(async {
+            let shutdown = Shutdown::new();
+            shutdown.spawn(sleep(Duration::from_millis(10)));
+            shutdown.spawn(sleep(Duration::from_secs(60)));
+            shutdown.handle().shutdown();
+            shutdown.triggered().await;
+
+            let start = Instant::now();
+            assert_eq!(shutdown.drain(Duration::from_millis(100)).await, 1);
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert_eq!(shutdown.in_flight(), 0);
+        })
+        .unwrap();
+    }
+}
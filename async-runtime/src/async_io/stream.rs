@@ -0,0 +1,229 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An asynchronous sequence of values, the async counterpart to `Iterator`.
+pub trait Stream {
+    type Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+}
+
+impl<S: Stream + Unpin + ?Sized> Stream for &mut S {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut **self.get_mut()).poll_next(cx)
+    }
+}
+
+impl<S: Stream + ?Sized> Stream for Pin<Box<S>> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().as_mut().poll_next(cx)
+    }
+}
+
+pub trait StreamExt: Stream {
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Next { stream: self }
+    }
+
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> T,
+    {
+        Map { stream: self, f }
+    }
+
+    fn filter<F>(self, f: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Filter { stream: self, f }
+    }
+
+    fn take(self, n: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take {
+            stream: self,
+            remaining: n,
+        }
+    }
+}
+
+impl<S: Stream + ?Sized> StreamExt for S {}
+
+/// Turns an iterator into a stream that is always ready.
+pub fn iter<I: IntoIterator>(i: I) -> Iter<I::IntoIter> {
+    Iter {
+        iter: i.into_iter(),
+    }
+}
+
+pub struct Iter<I> {
+    iter: I,
+}
+
+impl<I: Iterator + Unpin> Stream for Iter<I> {
+    type Item = I::Item;
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.get_mut().iter.next())
+    }
+}
+
+/// A stream whose values come from repeatedly running `f` on a state, until
+/// the future it returns resolves to `None`.
+pub fn unfold<T, F, Fut, Item>(init: T, f: F) -> Unfold<T, F, Fut>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Option<(Item, T)>>,
+{
+    Unfold {
+        state: Some(init),
+        f,
+        future: None,
+    }
+}
+
+pub struct Unfold<T, F, Fut> {
+    state: Option<T>,
+    f: F,
+    future: Option<Pin<Box<Fut>>>,
+}
+
+impl<T, F, Fut, Item> Stream for Unfold<T, F, Fut>
+where
+    F: FnMut(T) -> Fut + Unpin,
+    Fut: Future<Output = Option<(Item, T)>>,
+    T: Unpin,
+{
+    type Item = Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(state) = this.state.take() {
+            this.future = Some(Box::pin((this.f)(state)));
+        }
+        let future = match this.future.as_mut() {
+            Some(future) => future,
+            // finished
+            None => return Poll::Ready(None),
+        };
+        match future.as_mut().poll(cx) {
+            Poll::Ready(Some((item, state))) => {
+                this.future = None;
+                this.state = Some(state);
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => {
+                this.future = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct Next<'stream, S: ?Sized> {
+    stream: &'stream mut S,
+}
+
+impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut *self.get_mut().stream).poll_next(cx)
+    }
+}
+
+pub struct Map<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S, F, T> Stream for Map<S, F>
+where
+    S: Stream + Unpin,
+    F: FnMut(S::Item) -> T + Unpin,
+{
+    type Item = T;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let state = self.get_mut();
+        Pin::new(&mut state.stream)
+            .poll_next(cx)
+            .map(|item| item.map(&mut state.f))
+    }
+}
+
+pub struct Filter<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S, F> Stream for Filter<S, F>
+where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> bool + Unpin,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let state = self.get_mut();
+        loop {
+            match Pin::new(&mut state.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) if !(state.f)(&item) => continue,
+                poll => return poll,
+            }
+        }
+    }
+}
+
+pub struct Take<S> {
+    stream: S,
+    remaining: usize,
+}
+
+impl<S: Stream + Unpin> Stream for Take<S> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let state = self.get_mut();
+        if state.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let poll = Pin::new(&mut state.stream).poll_next(cx);
+        match poll {
+            Poll::Ready(Some(_)) => state.remaining -= 1,
+            Poll::Ready(None) => state.remaining = 0,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_io::executor;
+
+    #[test]
+    fn test_adapters() {
+        executor::block_on(async {
+            let mut stream = iter(1..10).filter(|n| n % 2 == 0).map(|n| n * 10).take(2);
+            assert_eq!(stream.next().await, Some(20));
+            assert_eq!(stream.next().await, Some(40));
+            assert_eq!(stream.next().await, None);
+
+            let mut stream = unfold(0, |n| async move { (n < 3).then_some((n * 2, n + 1)) });
+            assert_eq!(stream.next().await, Some(0));
+            assert_eq!(stream.next().await, Some(2));
+            assert_eq!(stream.next().await, Some(4));
+            assert_eq!(stream.next().await, None);
+            assert_eq!(stream.next().await, None);
+        })
+        .unwrap();
+    }
+}
@@ -4,6 +4,7 @@ use std::pin::Pin;
 use std::rc::Rc;
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::task::Waker;
 
 pub type LocalBoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
 
@@ -14,7 +15,15 @@ pub struct TaskQueue {
 }
 
 pub struct Task {
-    pub future: RefCell<LocalBoxedFuture<'static, ()>>,
+    // taken once the task completes or is cancelled
+    pub future: RefCell<Option<LocalBoxedFuture<'static, ()>>>,
+    pub waker: RefCell<Option<Waker>>,
+}
+
+impl Default for TaskQueue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TaskQueue {
@@ -35,8 +44,8 @@ impl TaskQueue {
         self.tasks.pop()
     }
 
-    pub fn push(&mut self, runnable: Task) {
-        self.tasks.push(Rc::new(runnable));
+    pub fn push(&mut self, runnable: Rc<Task>) {
+        self.tasks.push(runnable);
     }
 
     pub fn receive(&mut self) {
@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::reactor::REACTOR;
+
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline, id: None }
+}
+
+/// Runs `future` but gives up with a `TimedOut` error (dropping the future)
+/// if it hasn't completed within `duration`.
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    timeout_at(Instant::now() + duration, future)
+}
+
+pub fn timeout_at<F: Future>(deadline: Instant, future: F) -> Timeout<F> {
+    Timeout {
+        future: Box::pin(future),
+        sleep: sleep_until(deadline),
+    }
+}
+
+/// Resolves once the deadline passes. The reactor caps how long it blocks
+/// on I/O by the nearest pending deadline.
+pub struct Sleep {
+    deadline: Instant,
+    id: Option<usize>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        if Instant::now() >= state.deadline {
+            if let Some(id) = state.id.take() {
+                REACTOR.with(|current| current.borrow_mut().remove_timer(state.deadline, id));
+            }
+            return Poll::Ready(());
+        }
+
+        REACTOR.with(|current| {
+            let mut current = current.borrow_mut();
+            let id = match state.id {
+                Some(id) => id,
+                None => *state.id.insert(current.timer_id()),
+            };
+            current.wake_at(state.deadline, id, cx);
+        });
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            REACTOR.with(|current| current.borrow_mut().remove_timer(self.deadline, id));
+        }
+    }
+}
+
+pub struct Timeout<F> {
+    future: Pin<Box<F>>,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = io::Result<F::Output>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        if let Poll::Ready(output) = state.future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        match Pin::new(&mut state.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
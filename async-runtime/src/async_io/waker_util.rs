@@ -22,7 +22,7 @@ impl<F: Fn() + 'static> WakerHelper<F> {
 
     unsafe fn clone_waker(ptr: *const ()) -> RawWaker {
         let arc = ManuallyDrop::new(Arc::from_raw(ptr as *const F));
-        std::mem::forget(arc.clone());
+        std::mem::forget(Arc::clone(&arc));
         RawWaker::new(ptr, &Self::VTABLE)
     }
 
@@ -1,88 +1,69 @@
-use std::io::{ErrorKind, Read, Result, Write};
-use std::{
-    future::Future,
-    net,
-    pin::Pin,
-    task::{Context, Poll},
-};
+use std::io::{ErrorKind, Result, Write};
+use std::net;
+use std::time::Duration;
 
+use crate::async_io::io::{ReadFuture, WriteFuture};
 use crate::async_io::reactor::REACTOR;
+use crate::async_io::timer::timeout;
 
 pub struct TcpClient {
     stream: net::TcpStream,
+    write_timeout: Option<Duration>,
 }
 
 impl TcpClient {
     pub fn new(stream: net::TcpStream) -> Self {
-        TcpClient { stream }
+        TcpClient {
+            stream,
+            write_timeout: None,
+        }
+    }
+
+    /// Another handle to the same connection, so a response can be written
+    /// while the router keeps reading requests off of it.
+    pub fn try_clone(&self) -> Result<TcpClient> {
+        Ok(TcpClient {
+            stream: self.stream.try_clone()?,
+            write_timeout: self.write_timeout,
+        })
     }
 
-    pub fn read<'stream, T: AsMut<[u8]>>(
+    /// Fails `write_all` with `TimedOut` when the peer hasn't taken any of
+    /// the data for this long.
+    pub fn set_write_timeout(&mut self, write_timeout: Option<Duration>) {
+        self.write_timeout = write_timeout;
+    }
+
+    pub fn read<'stream, T: AsMut<[u8]> + ?Sized>(
         &'stream mut self,
         buf: &'stream mut T,
-    ) -> ReadFuture<'stream> {
-        ReadFuture {
-            stream: &mut self.stream,
-            buf: buf.as_mut(),
-        }
+    ) -> ReadFuture<'stream, net::TcpStream> {
+        ReadFuture::new(&mut self.stream, buf.as_mut())
     }
 
-    pub fn write<'stream, T: AsRef<[u8]>>(
+    pub fn write<'stream, T: AsRef<[u8]> + ?Sized>(
         &'stream mut self,
         buf: &'stream T,
-    ) -> WriteFuture<'stream> {
-        WriteFuture {
-            stream: &mut self.stream,
-            buf: buf.as_ref(),
-        }
+    ) -> WriteFuture<'stream, net::TcpStream> {
+        WriteFuture::new(&mut self.stream, buf.as_ref())
     }
 
-    pub fn flush(&mut self) {
-        self.stream.flush().unwrap();
-    }
-}
-
-pub struct WriteFuture<'stream> {
-    stream: &'stream mut net::TcpStream,
-    buf: &'stream [u8],
-}
-
-impl Future for WriteFuture<'_> {
-    type Output = Result<usize>;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let state = self.get_mut();
-        match state.stream.write(state.buf) {
-            Ok(n) => Poll::Ready(Ok(n)),
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                REACTOR.with(|current| {
-                    current.borrow_mut().wake_on_writable(&*state.stream, cx);
-                });
-                Poll::Pending
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let written = match self.write_timeout {
+                Some(write_timeout) => timeout(write_timeout, self.write(buf)).await?,
+                None => self.write(buf).await,
+            };
+            match written? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
             }
-            Err(e) => Poll::Ready(Err(e)),
         }
+        Ok(())
     }
-}
-
-pub struct ReadFuture<'stream> {
-    stream: &'stream mut net::TcpStream,
-    buf: &'stream mut [u8],
-}
 
-impl Future for ReadFuture<'_> {
-    type Output = Result<usize>;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        let state = self.get_mut();
-        match state.stream.read(&mut state.buf) {
-            Ok(n) => Poll::Ready(Ok(n)),
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                REACTOR.with(|current| {
-                    current.borrow_mut().wake_on_readable(&*state.stream, cx);
-                });
-                Poll::Pending
-            }
-            Err(e) => Poll::Ready(Err(e)),
-        }
+    pub fn flush(&mut self) {
+        self.stream.flush().unwrap();
     }
 }
 
@@ -1,12 +1,15 @@
 use std::future::Future;
 use std::io::{self, Result};
-use std::net;
+use std::mem;
+use std::net::{self, SocketAddr, ToSocketAddrs};
+use std::os::unix::io::FromRawFd;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use colored::Colorize;
 
 use crate::async_io::reactor::REACTOR;
+use crate::async_io::stream::Stream;
 
 use super::client::TcpClient;
 
@@ -21,9 +24,69 @@ impl TcpListener {
         Ok(TcpListener { listener })
     }
 
-    pub fn accept(&self) -> Accept {
+    /// Binds with `SO_REUSEPORT` so several listeners (one per executor
+    /// thread) can share the address and the kernel balances between them.
+    pub fn bind_reuse_port(addr: &str) -> Result<TcpListener> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to bind"))?;
+
+        let domain = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = check_err(unsafe {
+            libc::socket(
+                domain,
+                libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                0,
+            )
+        })?;
+        // owned straight away so the socket is closed if any step below fails
+        let listener = unsafe { net::TcpListener::from_raw_fd(fd) };
+
+        let enable: libc::c_int = 1;
+        for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+            check_err(unsafe {
+                libc::setsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    option,
+                    &enable as *const libc::c_int as *const libc::c_void,
+                    mem::size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            })?;
+        }
+
+        let (storage, len) = sockaddr(&addr);
+        check_err(unsafe { libc::bind(fd, &storage as *const _ as *const libc::sockaddr, len) })?;
+        check_err(unsafe { libc::listen(fd, 1024) })?;
+
+        Ok(TcpListener { listener })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// A stream of incoming connections. The listener stays registered with
+    /// the reactor for as long as the stream is alive, rather than being
+    /// added and removed around every single accept.
+    pub fn incoming(&self) -> Incoming<'_> {
+        REACTOR.with(|current| {
+            let mut current = current.borrow_mut();
+            current.add(&self.listener);
+        });
+
+        Incoming {
+            listener: &self.listener,
+        }
+    }
+
+    pub fn accept(&self) -> Accept<'_> {
         REACTOR.with(|current| {
-            let current = current.borrow();
+            let mut current = current.borrow_mut();
             current.add(&self.listener);
         });
 
@@ -40,22 +103,7 @@ pub struct Accept<'listener> {
 impl Future for Accept<'_> {
     type Output = Result<(TcpClient, net::SocketAddr)>;
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        match self.listener.accept() {
-            Ok((stream, addr)) => Poll::Ready(Ok((TcpClient::new(stream), addr))),
-            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                println!(
-                    "{} {:?} tcp listener 127.0.0.1:7000 accept() would block, pending future",
-                    format!("[{}]", std::process::id()).truecolor(0, 255, 136),
-                    std::thread::current().id()
-                );
-                REACTOR.with(|current| {
-                    let mut current = current.borrow_mut();
-                    current.wake_on_readable(self.listener, cx);
-                });
-                Poll::Pending
-            }
-            Err(e) => Poll::Ready(Err(e)),
-        }
+        poll_accept(self.listener, cx)
     }
 }
 
@@ -67,3 +115,110 @@ impl Drop for Accept<'_> {
         });
     }
 }
+
+pub struct Incoming<'listener> {
+    listener: &'listener net::TcpListener,
+}
+
+impl Incoming<'_> {
+    /// Stops listening for new connections until the stream is polled again,
+    /// leaving them queued in the kernel's accept backlog.
+    pub fn pause(&self) {
+        REACTOR.with(|current| {
+            let mut current = current.borrow_mut();
+            current.clear_interest(self.listener);
+        });
+    }
+}
+
+impl Stream for Incoming<'_> {
+    type Item = Result<(TcpClient, net::SocketAddr)>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        poll_accept(self.listener, cx).map(Some)
+    }
+}
+
+impl Drop for Incoming<'_> {
+    fn drop(&mut self) {
+        REACTOR.with(|current| {
+            let mut current = current.borrow_mut();
+            current.remove(self.listener);
+        });
+    }
+}
+
+fn poll_accept(
+    listener: &net::TcpListener,
+    cx: &mut Context,
+) -> Poll<Result<(TcpClient, net::SocketAddr)>> {
+    match listener.accept() {
+        Ok((stream, addr)) => {
+            // accepted sockets don't inherit the listener's non-blocking mode
+            if let Err(e) = stream.set_nonblocking(true) {
+                return Poll::Ready(Err(e));
+            }
+            Poll::Ready(Ok((TcpClient::new(stream), addr)))
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            println!(
+                "{} {:?} tcp listener 127.0.0.1:7000 accept() would block, pending future",
+                format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                std::thread::current().id()
+            );
+            REACTOR.with(|current| {
+                let mut current = current.borrow_mut();
+                current.wake_on_readable(listener, cx);
+            });
+            Poll::Pending
+        }
+        Err(e) => Poll::Ready(Err(e)),
+    }
+}
+
+fn check_err(num: libc::c_int) -> Result<libc::c_int> {
+    if num < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(num)
+}
+
+fn sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr = libc::in_addr {
+                s_addr: u32::from_ne_bytes(addr.ip().octets()),
+            };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_addr = libc::in6_addr {
+                s6_addr: addr.ip().octets(),
+            };
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_reuse_port() {
+        let first = TcpListener::bind_reuse_port("127.0.0.1:0").unwrap();
+        let addr = first.local_addr().unwrap().to_string();
+        let second = TcpListener::bind_reuse_port(&addr).unwrap();
+        assert_eq!(second.local_addr().unwrap(), first.local_addr().unwrap());
+        assert!(TcpListener::bind(&addr).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use std::future::Future;
+use std::io::{self, Read, Result, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::process::{self, ExitStatus};
+use std::task::{Context, Poll};
+
+use colored::Colorize;
+
+use crate::async_io::io::{ReadFuture, WriteFuture};
+use crate::async_io::reactor::REACTOR;
+
+pub type ChildStdin = Pipe<process::ChildStdin>;
+pub type ChildStdout = Pipe<process::ChildStdout>;
+pub type ChildStderr = Pipe<process::ChildStderr>;
+
+pub struct Child {
+    child: process::Child,
+    pidfd: OwnedFd,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+}
+
+impl Child {
+    pub fn new(mut child: process::Child) -> Result<Self> {
+        // a pidfd becomes readable once the child exits, which lets the
+        // reactor wake us up instead of blocking on waitpid
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, child.id(), 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let pidfd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+        Ok(Child {
+            stdin: child.stdin.take().map(Pipe::new).transpose()?,
+            stdout: child.stdout.take().map(Pipe::new).transpose()?,
+            stderr: child.stderr.take().map(Pipe::new).transpose()?,
+            child,
+            pidfd,
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn kill(&mut self) -> Result<()> {
+        self.child.kill()
+    }
+
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+
+    pub fn wait(&mut self) -> WaitFuture<'_> {
+        // close stdin so the child isn't left waiting on input we'll never send
+        self.stdin.take();
+        WaitFuture {
+            child: &mut self.child,
+            pidfd: &self.pidfd,
+        }
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        REACTOR.with(|current| {
+            let mut current = current.borrow_mut();
+            current.remove(&self.pidfd);
+        });
+    }
+}
+
+pub struct WaitFuture<'child> {
+    child: &'child mut process::Child,
+    pidfd: &'child OwnedFd,
+}
+
+impl Future for WaitFuture<'_> {
+    type Output = Result<ExitStatus>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let state = self.get_mut();
+        match state.child.try_wait() {
+            Ok(Some(status)) => Poll::Ready(Ok(status)),
+            Ok(None) => {
+                println!(
+                    "{} {:?} child process {} still running, pending future",
+                    format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                    std::thread::current().id(),
+                    state.child.id()
+                );
+                REACTOR.with(|current| {
+                    current.borrow_mut().wake_on_readable(state.pidfd, cx);
+                });
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// One end of a child's stdio pipe, switched to non-blocking mode.
+pub struct Pipe<T: AsRawFd> {
+    io: T,
+}
+
+impl<T: AsRawFd> Pipe<T> {
+    fn new(io: T) -> Result<Self> {
+        let fd = io.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Pipe { io })
+    }
+}
+
+impl<T: AsRawFd + Read> Pipe<T> {
+    pub fn read<'pipe, B: AsMut<[u8]>>(&'pipe mut self, buf: &'pipe mut B) -> ReadFuture<'pipe, T> {
+        ReadFuture::new(&mut self.io, buf.as_mut())
+    }
+
+    pub async fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<usize> {
+        let mut buf = [0; 4096];
+        let mut total = 0;
+        loop {
+            match self.read(&mut buf).await? {
+                0 => return Ok(total),
+                n => {
+                    out.extend_from_slice(&buf[..n]);
+                    total += n;
+                }
+            }
+        }
+    }
+}
+
+impl<T: AsRawFd + Write> Pipe<T> {
+    pub fn write<'pipe, B: AsRef<[u8]>>(&'pipe mut self, buf: &'pipe B) -> WriteFuture<'pipe, T> {
+        WriteFuture::new(&mut self.io, buf.as_ref())
+    }
+
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(&buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: AsRawFd> Drop for Pipe<T> {
+    fn drop(&mut self) {
+        REACTOR.with(|current| {
+            let mut current = current.borrow_mut();
+            current.remove(&self.io);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_io::executor;
+    use crate::async_process::command::Command;
+
+    #[test]
+    fn test_pipe_through_child() {
+        executor::block_on(async {
+            let mut child = Command::new("cat").spawn().unwrap();
+            let mut stdin = child.stdin.take().unwrap();
+            stdin.write_all(b"hello child").await.unwrap();
+            drop(stdin);
+
+            let mut out = Vec::new();
+            let mut stdout = child.stdout.take().unwrap();
+            stdout.read_to_end(&mut out).await.unwrap();
+            assert_eq!(out, b"hello child");
+
+            let status = child.wait().await.unwrap();
+            assert!(status.success());
+        })
+        .unwrap();
+    }
+}
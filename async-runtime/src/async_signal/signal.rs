@@ -0,0 +1,257 @@
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, ErrorKind, Read, Result};
+use std::os::unix::io::{FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use colored::Colorize;
+
+use crate::async_io::reactor::REACTOR;
+use crate::async_io::stream::Stream;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignalKind {
+    Interrupt,
+    Terminate,
+    Hangup,
+}
+
+impl SignalKind {
+    pub fn as_raw(&self) -> libc::c_int {
+        match self {
+            SignalKind::Interrupt => libc::SIGINT,
+            SignalKind::Terminate => libc::SIGTERM,
+            SignalKind::Hangup => libc::SIGHUP,
+        }
+    }
+}
+
+const MAX_LISTENERS: usize = 64;
+
+// each listener owns the write end of a self-pipe; the signal handler can't
+// take locks or allocate so the table is a fixed array of atomics
+struct Listener {
+    signo: AtomicI32,
+    fd: AtomicI32,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: Listener = Listener {
+    signo: AtomicI32::new(0),
+    fd: AtomicI32::new(-1),
+};
+
+static LISTENERS: [Listener; MAX_LISTENERS] = [EMPTY; MAX_LISTENERS];
+
+// our handler is installed for a signal for as long as it has listeners,
+// and the disposition it replaced is put back when the last one goes
+struct Installed {
+    listeners: usize,
+    previous: libc::sigaction,
+}
+
+static INSTALLED: Mutex<[Option<Installed>; 32]> = Mutex::new([const { None }; 32]);
+
+extern "C" fn handler(signo: libc::c_int) {
+    let errno = unsafe { *libc::__errno_location() };
+    for listener in LISTENERS.iter() {
+        if listener.signo.load(Ordering::Acquire) != signo {
+            continue;
+        }
+        let fd = listener.fd.load(Ordering::Acquire);
+        if fd >= 0 {
+            let byte = signo as u8;
+            unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) };
+        }
+    }
+    unsafe { *libc::__errno_location() = errno };
+}
+
+fn install(signo: libc::c_int) -> Result<()> {
+    let mut installed = INSTALLED.lock().unwrap();
+    if let Some(installed) = &mut installed[signo as usize] {
+        installed.listeners += 1;
+        return Ok(());
+    }
+    let previous = unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(signo, &action, &mut previous) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        previous
+    };
+    installed[signo as usize] = Some(Installed {
+        listeners: 1,
+        previous,
+    });
+    Ok(())
+}
+
+fn uninstall(signo: libc::c_int) {
+    let mut installed = INSTALLED.lock().unwrap();
+    let slot = &mut installed[signo as usize];
+    if let Some(installed) = slot {
+        installed.listeners -= 1;
+        if installed.listeners == 0 {
+            unsafe { libc::sigaction(signo, &installed.previous, std::ptr::null_mut()) };
+            *slot = None;
+        }
+    }
+}
+
+/// Listens for a unix signal, delivered through a self-pipe registered with the reactor.
+///
+/// Installing a listener replaces the default disposition for that signal,
+/// so e.g. Ctrl-C no longer kills the process while one is alive. The
+/// disposition it replaced is put back once the last listener for the
+/// signal is dropped.
+pub struct Signal {
+    kind: SignalKind,
+    slot: usize,
+    reader: File,
+    _writer: OwnedFd,
+}
+
+pub fn signal(kind: SignalKind) -> Result<Signal> {
+    let mut fds: [RawFd; 2] = [-1; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let reader = unsafe { File::from_raw_fd(fds[0]) };
+    let writer = unsafe { OwnedFd::from_raw_fd(fds[1]) };
+
+    let slot = LISTENERS
+        .iter()
+        .position(|listener| {
+            listener
+                .fd
+                .compare_exchange(-1, fds[1], Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        })
+        .ok_or_else(|| io::Error::other("too many signal listeners"))?;
+    LISTENERS[slot]
+        .signo
+        .store(kind.as_raw(), Ordering::Release);
+
+    if let Err(e) = install(kind.as_raw()) {
+        LISTENERS[slot].signo.store(0, Ordering::Release);
+        LISTENERS[slot].fd.store(-1, Ordering::Release);
+        return Err(e);
+    }
+    Ok(Signal {
+        kind,
+        slot,
+        reader,
+        _writer: writer,
+    })
+}
+
+impl Signal {
+    pub fn kind(&self) -> SignalKind {
+        self.kind
+    }
+
+    /// Resolves the next time the signal is delivered. Signals that arrive
+    /// in a burst before we get to read them are coalesced into one.
+    pub fn recv(&mut self) -> Recv<'_> {
+        Recv { signal: self }
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<()>> {
+        let mut buf = [0; 32];
+        let mut received = false;
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => return Poll::Ready(None),
+                Ok(_) => received = true,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return Poll::Ready(None),
+            }
+        }
+
+        if received {
+            println!(
+                "{} {:?} received signal {:?}",
+                format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                std::thread::current().id(),
+                self.kind
+            );
+            return Poll::Ready(Some(()));
+        }
+
+        REACTOR.with(|current| {
+            current.borrow_mut().wake_on_readable(&self.reader, cx);
+        });
+        Poll::Pending
+    }
+}
+
+impl Drop for Signal {
+    fn drop(&mut self) {
+        LISTENERS[self.slot].signo.store(0, Ordering::Release);
+        LISTENERS[self.slot].fd.store(-1, Ordering::Release);
+        uninstall(self.kind.as_raw());
+        REACTOR.with(|current| {
+            let mut current = current.borrow_mut();
+            current.remove(&self.reader);
+        });
+    }
+}
+
+pub struct Recv<'signal> {
+    signal: &'signal mut Signal,
+}
+
+impl Future for Recv<'_> {
+    type Output = Option<()>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().signal.poll_recv(cx)
+    }
+}
+
+impl Stream for Signal {
+    type Item = ();
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_io::executor;
+
+    fn disposition(signo: libc::c_int) -> libc::sighandler_t {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signo, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        }
+    }
+
+    #[test]
+    fn test_recv_raised_signal() {
+        let before = disposition(libc::SIGHUP);
+        executor::block_on(async move {
+            let mut hangup = signal(SignalKind::Hangup).unwrap();
+            let mut again = signal(SignalKind::Hangup).unwrap();
+            unsafe { libc::raise(libc::SIGHUP) };
+            assert_eq!(hangup.recv().await, Some(()));
+            assert_eq!(again.recv().await, Some(()));
+
+            // still handled until the last listener is gone
+            drop(hangup);
+            assert_ne!(disposition(libc::SIGHUP), before);
+        })
+        .unwrap();
+        assert_eq!(disposition(libc::SIGHUP), before);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod async_io;
+pub mod async_net;
+pub mod async_process;
+pub mod async_signal;
+pub mod web;
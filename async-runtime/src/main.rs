@@ -1,22 +1,67 @@
-mod async_io;
-mod async_net;
-mod web;
-
-use crate::async_io::executor;
-use crate::async_net::listener::TcpListener;
-use crate::web::router::Router;
-use crate::web::routes;
+use async_runtime::async_io::executor;
+use async_runtime::async_signal::signal::SignalKind;
+use async_runtime::web::router::Router;
+use async_runtime::web::routes;
+use async_runtime::web::server::{Overflow, Server, ServerConfig};
+use std::env;
 use std::io::Result;
+use std::thread;
 
-fn main() -> Result<()> {
-    executor::block_on(async {
-        let listener = TcpListener::bind("127.0.0.1:7000").unwrap();
-        while let Ok((client, addr)) = listener.accept().await {
-            executor::spawn(async {
-                let mut router = Router::new();
-                routes::configure(&mut router);
-                router.route_client(client).await.unwrap();
-            });
+// usage: http-sync-server [--workers N] [--max-connections N] [--reject-overflow]
+fn parse_args() -> (ServerConfig, usize) {
+    let mut config = ServerConfig::default();
+    let mut workers = 1;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // 0 starts one executor per core
+            "--workers" => {
+                workers = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(0) => thread::available_parallelism().map_or(1, |n| n.get()),
+                    Some(n) => n,
+                    None => 1,
+                };
+            }
+            "--max-connections" => {
+                config.max_connections = args.next().and_then(|n| n.parse().ok());
+            }
+            "--reject-overflow" => config.overflow = Overflow::Reject,
+            _ => eprintln!("ignoring unknown argument {}", arg),
         }
+    }
+    (config, workers)
+}
+
+fn run(config: ServerConfig) -> Result<()> {
+    executor::block_on(async {
+        let mut router = Router::new();
+        routes::configure(&mut router);
+
+        let server = Server::bind("127.0.0.1:7000", config).unwrap();
+        server.shutdown().on_signal(SignalKind::Interrupt).unwrap();
+        server.shutdown().on_signal(SignalKind::Terminate).unwrap();
+        server.serve(router).await;
     })
 }
+
+fn main() -> Result<()> {
+    let (mut config, workers) = parse_args();
+    if workers <= 1 {
+        return run(config);
+    }
+
+    // each thread gets its own executor, reactor and listener, the kernel
+    // spreads incoming connections across the listeners
+    config.reuse_port = true;
+    let threads: Vec<_> = (0..workers)
+        .map(|_| {
+            let config = config.clone();
+            thread::spawn(move || run(config))
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, ErrorKind, Result};
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::async_io::timer::timeout;
+
+use super::connection::Connection;
+use super::headers::Headers;
+use super::parser::{parse_chunk_size, parse_header_line};
+
+/// How the length of a request body is determined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyKind {
+    Empty,
+    Length(u64),
+    Chunked,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Length(u64),
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd,
+    Trailers,
+    Done,
+}
+
+// chunk size lines and trailers have no business being longer than this
+const MAX_LINE: usize = 8 * 1024;
+
+/// Where a body hands its connection back to the router once it's dropped,
+/// so the next request on a keep-alive connection can be read.
+pub type BodySlot = Rc<RefCell<Option<Body>>>;
+
+/// Decodes a request body off of the connection, either up to the
+/// `Content-Length` or through each chunk of a chunked transfer encoding.
+pub struct Body {
+    state: State,
+    read_timeout: Duration,
+    // decoded bytes read so far, and the most there may be
+    read: u64,
+    limit: u64,
+    trailers: Headers,
+    conn: Option<Connection>,
+    slot: Option<BodySlot>,
+}
+
+impl Body {
+    pub fn new(kind: BodyKind, conn: Connection, read_timeout: Duration) -> Self {
+        let state = match kind {
+            BodyKind::Empty => State::Done,
+            BodyKind::Length(n) => State::Length(n),
+            BodyKind::Chunked => State::ChunkSize,
+        };
+        Body {
+            state,
+            read_timeout,
+            read: 0,
+            limit: u64::MAX,
+            trailers: Headers::new(),
+            conn: Some(conn),
+            slot: None,
+        }
+    }
+
+    /// A body with nothing to read and no connection behind it.
+    pub fn empty() -> Self {
+        Body {
+            state: State::Done,
+            read_timeout: Duration::ZERO,
+            read: 0,
+            limit: u64::MAX,
+            trailers: Headers::new(),
+            conn: None,
+            slot: None,
+        }
+    }
+
+    /// Puts the body, with its connection and whatever is left unread,
+    /// into `slot` when it's dropped.
+    pub fn return_to(&mut self, slot: BodySlot) {
+        self.slot = Some(slot);
+    }
+
+    /// Fails reads with `FileTooLarge` once the body would go past `limit`
+    /// bytes, the same for a length as for chunks.
+    pub fn set_limit(&mut self, limit: u64) {
+        self.limit = limit;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Trailer fields sent after the last chunk, available once the body is done.
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    /// Reads the next part of the body into `buf`, returning 0 at the end.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read_timeout = self.read_timeout;
+        loop {
+            if self.state == State::Done {
+                return Ok(0);
+            }
+            let conn = self.conn.as_mut().ok_or(ErrorKind::NotConnected)?;
+            match self.state {
+                State::Done => return Ok(0),
+                State::Length(remaining) | State::ChunkData(remaining) => {
+                    // a chunk size can be anything up to `u64::MAX`
+                    if remaining > self.limit.saturating_sub(self.read) {
+                        return Err(too_large());
+                    }
+                    if conn.buffered().is_empty() {
+                        fill(conn, read_timeout).await?;
+                    }
+                    let n = conn.buffered().len().min(buf.len()).min(remaining as usize);
+                    buf[..n].copy_from_slice(&conn.buffered()[..n]);
+                    conn.consume(n);
+                    self.read += n as u64;
+
+                    let remaining = remaining - n as u64;
+                    self.state = match self.state {
+                        State::Length(_) if remaining == 0 => State::Done,
+                        State::Length(_) => State::Length(remaining),
+                        _ if remaining == 0 => State::ChunkEnd,
+                        _ => State::ChunkData(remaining),
+                    };
+                    return Ok(n);
+                }
+                State::ChunkSize => {
+                    let line = read_line(conn, read_timeout).await?;
+                    let size = parse_chunk_size(&line).map_err(invalid)?;
+                    self.state = if size == 0 {
+                        State::Trailers
+                    } else {
+                        State::ChunkData(size)
+                    };
+                }
+                State::ChunkEnd => {
+                    if !read_line(conn, read_timeout).await?.is_empty() {
+                        return Err(invalid("missing crlf after chunk"));
+                    }
+                    self.state = State::ChunkSize;
+                }
+                State::Trailers => {
+                    let line = read_line(conn, read_timeout).await?;
+                    if line.is_empty() {
+                        self.state = State::Done;
+                    } else {
+                        let (name, value) = parse_header_line(&line).map_err(invalid)?;
+                        self.trailers.append(&name, &value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the rest of the body onto `out`, failing if it's over `limit` bytes.
+    pub async fn read_to_end(&mut self, out: &mut Vec<u8>, limit: u64) -> Result<usize> {
+        let mut buf = [0; 4096];
+        let mut total = 0;
+        loop {
+            match self.read(&mut buf).await? {
+                0 => return Ok(total),
+                n if (total + n) as u64 > limit => return Err(too_large()),
+                n => {
+                    out.extend_from_slice(&buf[..n]);
+                    total += n;
+                }
+            }
+        }
+    }
+
+    /// Reads and discards whatever is left of the body, up to `limit` bytes.
+    pub async fn drain(&mut self, limit: u64) -> Result<()> {
+        let mut buf = [0; 4096];
+        let mut total = 0;
+        loop {
+            match self.read(&mut buf).await? {
+                0 => return Ok(()),
+                n if (total + n) as u64 > limit => return Err(too_large()),
+                n => total += n,
+            }
+        }
+    }
+
+    /// Takes back the connection, with anything read past the body still buffered.
+    pub fn into_connection(mut self) -> Option<Connection> {
+        self.slot = None;
+        self.conn.take()
+    }
+}
+
+impl Drop for Body {
+    fn drop(&mut self) {
+        if let (Some(slot), Some(conn)) = (self.slot.take(), self.conn.take()) {
+            let body = Body {
+                state: self.state,
+                read_timeout: self.read_timeout,
+                read: self.read,
+                limit: self.limit,
+                trailers: mem::take(&mut self.trailers),
+                conn: Some(conn),
+                slot: None,
+            };
+            *slot.borrow_mut() = Some(body);
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Body")
+            .field("state", &self.state)
+            .field("trailers", &self.trailers)
+            .finish_non_exhaustive()
+    }
+}
+
+async fn fill(conn: &mut Connection, read_timeout: Duration) -> Result<()> {
+    match timeout(read_timeout, conn.fill()).await?? {
+        0 => Err(ErrorKind::UnexpectedEof.into()),
+        _ => Ok(()),
+    }
+}
+
+async fn read_line(conn: &mut Connection, read_timeout: Duration) -> Result<Vec<u8>> {
+    loop {
+        if let Some(pos) = conn.buffered().iter().position(|b| *b == b'\n') {
+            let line = conn.buffered()[..pos].to_vec();
+            conn.consume(pos + 1);
+            return Ok(line.strip_suffix(b"\r").unwrap_or(&line).to_vec());
+        }
+        if conn.buffered().len() > MAX_LINE {
+            return Err(invalid("line too long"));
+        }
+        fill(conn, read_timeout).await?;
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(ErrorKind::FileTooLarge, "body too large")
+}
+
+fn invalid<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_io::executor;
+    use crate::web::test_util::connection;
+
+    #[test]
+    fn test_read_chunked_with_trailers() {
+        let conn =
+            connection(b"5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nExpires: never\r\n\r\nGET");
+        executor::block_on(async move {
+            let mut body = Body::new(BodyKind::Chunked, conn, Duration::from_secs(1));
+            let slot = BodySlot::default();
+            body.return_to(slot.clone());
+
+            let mut out = Vec::new();
+            body.read_to_end(&mut out, 1024).await.unwrap();
+            assert_eq!(out, b"hello, world");
+            assert!(body.is_done());
+            assert_eq!(body.trailers().get("expires"), Some("never"));
+
+            // the next pipelined request is left on the returned connection
+            drop(body);
+            let body = slot.borrow_mut().take().unwrap();
+            let conn = body.into_connection().unwrap();
+            assert_eq!(conn.buffered(), b"GET");
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_body_limit() {
+        let chunked = connection(b"5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n");
+        let length = connection(b"hello world");
+        let huge = connection(b"5\r\nhello\r\nffffffffffffffff\r\nworld\r\n0\r\n\r\n");
+        executor::block_on(async move {
+            let too_large =
+                |result: Result<usize>| result.unwrap_err().kind() == ErrorKind::FileTooLarge;
+
+            // the first chunk fits, the second would go past the limit
+            let mut body = Body::new(BodyKind::Chunked, chunked, Duration::from_secs(1));
+            body.set_limit(8);
+            let mut out = Vec::new();
+            assert!(too_large(body.read_to_end(&mut out, 1024).await));
+            assert_eq!(out, b"hello");
+
+            let mut body = Body::new(BodyKind::Length(11), length, Duration::from_secs(1));
+            body.set_limit(8);
+            assert!(too_large(body.read(&mut [0; 16]).await));
+
+            // a size that would overflow what's been read so far
+            let mut body = Body::new(BodyKind::Chunked, huge, Duration::from_secs(1));
+            let mut out = Vec::new();
+            assert!(too_large(body.read_to_end(&mut out, u64::MAX).await));
+            assert_eq!(out, b"hello");
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs::Metadata;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::date::{format_http_date, parse_http_date};
+use super::middleware::{Middleware, Next};
+use super::request::{Method, Request};
+use super::response::{Response, ResponseBody};
+
+/// Validators for a file, an `ETag` built from its size and modification
+/// time, and the `Last-Modified` date.
+pub fn validators(metadata: &Metadata) -> (String, Option<String>) {
+    let modified = metadata.modified().ok();
+    let since_epoch = modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or(Duration::ZERO);
+    let etag = format!("\"{:x}-{:x}\"", metadata.len(), since_epoch.as_nanos());
+    (etag, modified.map(format_http_date))
+}
+
+/// Whether an `If-None-Match` list matches `etag`, using the weak
+/// comparison that's required for GET requests.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| String::from(tag.trim().trim_start_matches("W/"));
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Answers GET and HEAD requests with `304 Not Modified` when the client's
+/// cached copy, identified by `If-None-Match` or `If-Modified-Since`, is
+/// still current according to the response's `ETag` or `Last-Modified`.
+pub struct ConditionalGet;
+
+impl Middleware for ConditionalGet {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> LocalBoxedFuture<'a, Response> {
+        let conditional = matches!(req.method, Method::GET | Method::HEAD);
+        let if_none_match = req.header("if-none-match").map(String::from);
+        let if_modified_since = req.header("if-modified-since").map(String::from);
+        Box::pin(async move {
+            let res = next.run(req).await;
+            if !conditional || res.status != 200 {
+                return res;
+            }
+            if is_not_modified(&res, if_none_match, if_modified_since) {
+                not_modified(res)
+            } else {
+                res
+            }
+        })
+    }
+}
+
+fn is_not_modified(
+    res: &Response,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+) -> bool {
+    // If-Modified-Since is ignored whenever If-None-Match is sent
+    if let Some(if_none_match) = if_none_match {
+        return res
+            .header("etag")
+            .is_some_and(|etag| etag_matches(&if_none_match, etag));
+    }
+    let last_modified = res.header("last-modified").and_then(parse_http_date);
+    let since = if_modified_since.as_deref().and_then(parse_http_date);
+    match (last_modified, since) {
+        (Some(last_modified), Some(since)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+// keeps the headers a cache needs to refresh its copy, drops the body
+fn not_modified(mut res: Response) -> Response {
+    res.status = 304;
+    res.body = ResponseBody::Empty;
+    res.headers.remove("content-type");
+    res.headers.remove("content-length");
+    res
+}
+
+/// Sets `Cache-Control` on responses that don't already have one, for a
+/// route, a group or the whole router.
+pub struct CacheControl {
+    value: String,
+}
+
+impl CacheControl {
+    pub fn new(value: &str) -> Self {
+        CacheControl {
+            value: String::from(value),
+        }
+    }
+}
+
+impl Middleware for CacheControl {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> LocalBoxedFuture<'a, Response> {
+        Box::pin(async move {
+            let mut res = next.run(req).await;
+            if !res.headers.contains("cache-control") {
+                res.set_header("cache-control", &self.value);
+            }
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conditions() {
+        let mut res = Response::new(200, "body");
+        res.set_header("etag", "\"abc\"");
+        res.set_header("last-modified", "Sun, 06 Nov 1994 08:49:37 GMT");
+        let header = |value: &str| Some(String::from(value));
+
+        assert!(is_not_modified(&res, header("W/\"abc\""), None));
+        assert!(is_not_modified(&res, header("\"x\", \"abc\""), None));
+        assert!(is_not_modified(&res, header("*"), None));
+        assert!(!is_not_modified(
+            &res,
+            header("\"x\""),
+            header("Sun, 06 Nov 1994 08:49:37 GMT")
+        ));
+        assert!(is_not_modified(
+            &res,
+            None,
+            header("Sun, 06 Nov 1994 08:49:37 GMT")
+        ));
+        assert!(!is_not_modified(
+            &res,
+            None,
+            header("Sun, 06 Nov 1994 08:49:36 GMT")
+        ));
+        assert!(!is_not_modified(&res, None, header("garbage")));
+    }
+}
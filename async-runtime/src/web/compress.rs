@@ -0,0 +1,486 @@
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+use crate::async_io::stream::Stream;
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::middleware::{Middleware, Next};
+use super::request::Request;
+use super::response::{BodyStream, Chunk, Response, ResponseBody};
+
+/// A content coding the server can produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// The extension of a precompressed sibling file, `styles.css.br`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+            Encoding::Deflate => "zz",
+        }
+    }
+
+    pub fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                // quality 5 is a fair trade between size and time for on the fly use
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(data)?;
+                Ok(encoder.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            // "deflate" in HTTP is the zlib format, not a raw deflate stream
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Picks the coding from `supported` (in order of preference) with the
+/// highest weight in an `Accept-Encoding` header, if any are acceptable.
+pub fn negotiate(accept_encoding: &str, supported: &[Encoding]) -> Option<Encoding> {
+    let weights: Vec<(String, f32)> = accept_encoding
+        .split(',')
+        .filter_map(|coding| {
+            let mut params = coding.split(';');
+            let name = params.next()?.trim().to_ascii_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse().ok())
+                .unwrap_or(1.0);
+            Some((name, q))
+        })
+        .collect();
+
+    let weight = |encoding: &Encoding| {
+        let find = |name: &str| weights.iter().find(|(n, _)| n == name).map(|(_, q)| *q);
+        let alias = match encoding {
+            Encoding::Gzip => find("x-gzip"),
+            _ => None,
+        };
+        find(encoding.name())
+            .or(alias)
+            .or_else(|| find("*"))
+            .unwrap_or(0.0)
+    };
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in supported {
+        let q = weight(encoding);
+        if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+            best = Some((*encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Adds `accept-encoding` to the `Vary` header of a response.
+pub fn vary_on_encoding(res: &mut Response) {
+    match res.header("vary").map(String::from) {
+        Some(vary)
+            if vary
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case("accept-encoding")) => {}
+        Some(vary) => res.set_header("vary", &format!("{}, accept-encoding", vary)),
+        None => res.set_header("vary", "accept-encoding"),
+    }
+}
+
+/// Compresses response bodies the client accepts a coding for, as long as
+/// they're of a compressible type and big enough to be worth it.
+pub struct Compression {
+    min_size: usize,
+    // exact types, or prefixes like `text/`
+    mime_types: Vec<String>,
+    encodings: Vec<Encoding>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        let mime_types = [
+            "text/",
+            "application/json",
+            "application/javascript",
+            "application/xml",
+            "application/wasm",
+            "image/svg+xml",
+        ];
+        Compression {
+            min_size: 1024,
+            mime_types: mime_types.iter().map(|m| String::from(*m)).collect(),
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+        }
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bodies smaller than this are sent as they are.
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Another type worth compressing, or a prefix of types ending in `/`.
+    pub fn mime_type(mut self, mime_type: &str) -> Self {
+        self.mime_types.push(String::from(mime_type));
+        self
+    }
+
+    /// The codings to offer, most preferred first.
+    pub fn encodings(mut self, encodings: &[Encoding]) -> Self {
+        self.encodings = encodings.to_vec();
+        self
+    }
+
+    fn is_compressible(&self, content_type: &str) -> bool {
+        let mime_type = content_type.split(';').next().unwrap_or_default().trim();
+        self.mime_types.iter().any(|m| {
+            if m.ends_with('/') {
+                mime_type.starts_with(m.as_str())
+            } else {
+                mime_type.eq_ignore_ascii_case(m)
+            }
+        })
+    }
+
+    // `ranged` is whether the request asked for a range, which is better
+    // served straight out of a file than by compressing the whole of it
+    fn compress(
+        &self,
+        mut res: Response,
+        accept_encoding: Option<String>,
+        ranged: bool,
+    ) -> Response {
+        let compressible = res
+            .header("content-type")
+            .is_some_and(|content_type| self.is_compressible(content_type));
+        if !compressible {
+            return res;
+        }
+        // caches have to keep compressed and plain copies apart
+        vary_on_encoding(&mut res);
+        if res.status != 200 || res.headers.contains("content-encoding") {
+            return res;
+        }
+
+        let encoding = match accept_encoding {
+            Some(accept) => match negotiate(&accept, &self.encodings) {
+                Some(encoding) => encoding,
+                None => return res,
+            },
+            None => return res,
+        };
+        res.body = match std::mem::take(&mut res.body) {
+            ResponseBody::Bytes(body) if body.len() >= self.min_size => {
+                match encoding.encode(&body) {
+                    Ok(encoded) if encoded.len() < body.len() => ResponseBody::Bytes(encoded),
+                    _ => {
+                        res.body = ResponseBody::Bytes(body);
+                        return res;
+                    }
+                }
+            }
+            // a file could be any size, so it's read and compressed a piece
+            // at a time as it's sent, and not at all if it never is, as
+            // when a 304 replaces it
+            ResponseBody::File(file) if !ranged && file.len() >= self.min_size as u64 => {
+                res.headers.remove("content-length");
+                ResponseBody::Stream(Box::pin(EncodeStream::new(file.into_stream(), encoding)))
+            }
+            // a stream's size isn't known, so it's compressed as it goes
+            ResponseBody::Stream(stream) => {
+                res.headers.remove("content-length");
+                ResponseBody::Stream(Box::pin(EncodeStream::new(stream, encoding)))
+            }
+            body => {
+                res.body = body;
+                return res;
+            }
+        };
+        res.set_header("content-encoding", encoding.name());
+        // the compressed bytes are a different representation with their own tag
+        if let Some(etag) = res.header("etag").map(String::from) {
+            let etag = match etag.strip_suffix('"') {
+                Some(tag) => format!("{}-{}\"", tag, encoding.name()),
+                None => etag,
+            };
+            res.set_header("etag", &etag);
+        }
+        res
+    }
+}
+
+// the output of an encoder, flushed after every chunk so a client sees data
+// as soon as the handler produces it
+enum Encoder {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                5,
+                22,
+            ))),
+            Encoding::Gzip => {
+                Encoder::Gzip(GzEncoder::new(Vec::new(), flate2::Compression::default()))
+            }
+            Encoding::Deflate => {
+                Encoder::Deflate(ZlibEncoder::new(Vec::new(), flate2::Compression::default()))
+            }
+        }
+    }
+
+    fn encode(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let output = match self {
+            Encoder::Brotli(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Deflate(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+        Ok(std::mem::take(output))
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+struct EncodeStream {
+    stream: BodyStream,
+    // taken once the body has ended
+    encoder: Option<Encoder>,
+    // trailers held back until the end of the compressed data is out
+    trailers: Option<Chunk>,
+}
+
+impl EncodeStream {
+    fn new(stream: BodyStream, encoding: Encoding) -> Self {
+        EncodeStream {
+            stream,
+            encoder: Some(Encoder::new(encoding)),
+            trailers: None,
+        }
+    }
+}
+
+impl Stream for EncodeStream {
+    type Item = io::Result<Chunk>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let encoder = match this.encoder.as_mut() {
+                Some(encoder) => encoder,
+                None => return Poll::Ready(this.trailers.take().map(Ok)),
+            };
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(Chunk::Data(data))) => match encoder.encode(&data) {
+                    Ok(encoded) if encoded.is_empty() => continue,
+                    Ok(encoded) => return Poll::Ready(Some(Ok(Chunk::Data(encoded)))),
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                end => {
+                    if let Some(Ok(trailers)) = end {
+                        this.trailers = Some(trailers);
+                    }
+                    let encoder = this.encoder.take().unwrap();
+                    return Poll::Ready(Some(encoder.finish().map(Chunk::Data)));
+                }
+            }
+        }
+    }
+}
+
+impl Middleware for Compression {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> LocalBoxedFuture<'a, Response> {
+        let accept_encoding = req.header("accept-encoding").map(String::from);
+        let ranged = req.headers.contains("range");
+        Box::pin(async move {
+            let res = next.run(req).await;
+            self.compress(res, accept_encoding, ranged)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+    use crate::async_io::executor;
+    use crate::async_io::stream::{self, StreamExt};
+    use crate::web::file::FileBody;
+
+    #[test]
+    fn test_negotiate() {
+        let all = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+        assert_eq!(negotiate("gzip, deflate, br", &all), Some(Encoding::Brotli));
+        assert_eq!(
+            negotiate("gzip;q=1.0, br;q=0.5", &all),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(negotiate("br;q=0, *", &all), Some(Encoding::Gzip));
+        assert_eq!(negotiate("x-gzip", &all), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity", &all), None);
+        assert_eq!(negotiate("*;q=0", &all), None);
+    }
+
+    #[test]
+    fn test_compress() {
+        let text = "hello compression ".repeat(100);
+        let mut res = Response::new(200, text.as_str());
+        res.set_header("content-type", "text/plain; charset=UTF-8");
+        res.set_header("etag", "\"abc\"");
+
+        let res = Compression::new().compress(res, Some(String::from("gzip")), false);
+        assert_eq!(res.header("content-encoding"), Some("gzip"));
+        assert_eq!(res.header("etag"), Some("\"abc-gzip\""));
+        assert_eq!(res.header("vary"), Some("accept-encoding"));
+
+        let mut decoded = String::new();
+        if let ResponseBody::Bytes(body) = &res.body {
+            GzDecoder::new(&body[..])
+                .read_to_string(&mut decoded)
+                .unwrap();
+        }
+        assert_eq!(decoded, text);
+
+        let chunks = stream::iter(vec![
+            Ok(Chunk::from(text.clone())),
+            Ok(Chunk::from(text.clone())),
+        ]);
+        let mut res = Response::new(200, ResponseBody::stream(chunks));
+        res.set_header("content-type", "text/plain");
+        let res = Compression::new().compress(res, Some(String::from("gzip")), false);
+        executor::block_on(async move {
+            let mut encoded = Vec::new();
+            if let ResponseBody::Stream(mut stream) = res.body {
+                while let Some(chunk) = stream.next().await {
+                    if let Chunk::Data(data) = chunk.unwrap() {
+                        encoded.extend(data);
+                    }
+                }
+            }
+            let mut decoded = String::new();
+            GzDecoder::new(&encoded[..])
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, text.repeat(2));
+        })
+        .unwrap();
+
+        let mut small = Response::new(200, "tiny");
+        small.set_header("content-type", "text/plain");
+        let small = Compression::new().compress(small, Some(String::from("gzip")), false);
+        assert!(!small.headers.contains("content-encoding"));
+    }
+
+    #[test]
+    fn test_compress_file() {
+        let path = std::env::temp_dir().join(format!("compress-{}", std::process::id()));
+        let text = "hello compression ".repeat(20_000);
+        fs::write(&path, &text).unwrap();
+        let file = || {
+            let body = FileBody::new(fs::File::open(&path).unwrap(), text.len() as u64);
+            Response::builder()
+                .content_type("text/plain")
+                .body(ResponseBody::File(body))
+        };
+
+        // a range is left to be read out of the file
+        let res = Compression::new().compress(file(), Some(String::from("gzip")), true);
+        assert!(matches!(res.body, ResponseBody::File(_)));
+        assert!(!res.headers.contains("content-encoding"));
+
+        // anything else is compressed as it's read, a piece at a time
+        let res = Compression::new().compress(file(), Some(String::from("gzip")), false);
+        assert_eq!(res.header("content-encoding"), Some("gzip"));
+        let ResponseBody::Stream(mut encoded) = res.body else {
+            panic!("expected the file to be streamed");
+        };
+        let ResponseBody::File(plain) = file().body else {
+            unreachable!()
+        };
+        let mut plain = plain.into_stream();
+        executor::block_on(async move {
+            let mut pieces = 0;
+            while let Some(chunk) = plain.next().await {
+                if let Chunk::Data(data) = chunk.unwrap() {
+                    assert!(data.len() <= 64 * 1024);
+                    pieces += 1;
+                }
+            }
+            assert!(pieces > 1);
+
+            let mut compressed = Vec::new();
+            while let Some(chunk) = encoded.next().await {
+                if let Chunk::Data(data) = chunk.unwrap() {
+                    compressed.extend(data);
+                }
+            }
+            let mut decoded = String::new();
+            GzDecoder::new(&compressed[..])
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, text);
+        })
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+/// Deadlines applied while serving a connection, so a slow or silent
+/// client can't hold on to a task and its socket forever.
+#[derive(Clone, Debug)]
+pub struct Timeouts {
+    // from the first byte of a request until its head has been read
+    pub header_read: Duration,
+    // between reads of a request body
+    pub body_read: Duration,
+    // waiting on an idle connection for the next request to start
+    pub keep_alive: Duration,
+    // the whole request, from its first byte until the response is sent,
+    // short of a streamed body
+    pub request: Duration,
+    // a single write the client takes none of, streamed bodies and
+    // upgraded connections included
+    pub write: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            header_read: Duration::from_secs(10),
+            body_read: Duration::from_secs(30),
+            keep_alive: Duration::from_secs(5),
+            request: Duration::from_secs(60),
+            write: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Size limits enforced while parsing requests.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    // request line and headers together
+    pub max_head_size: usize,
+    pub max_uri_length: usize,
+    pub max_headers: usize,
+    // a request body, whether sent with a length or chunked, unless its
+    // route sets its own with `Route::body_limit`
+    pub max_body_size: u64,
+    // requests served on one keep-alive connection before it's closed
+    pub max_requests: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_head_size: 8 * 1024,
+            max_uri_length: 2 * 1024,
+            max_headers: 100,
+            max_body_size: 1024 * 1024,
+            max_requests: 100,
+        }
+    }
+}
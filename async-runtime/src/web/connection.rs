@@ -0,0 +1,48 @@
+use std::io::Result;
+
+use crate::async_net::client::TcpClient;
+
+/// A client connection with a read buffer, so bytes read past the end of
+/// one part of a request (the head, a chunk) aren't lost to the next.
+pub struct Connection {
+    client: TcpClient,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(client: TcpClient) -> Self {
+        Connection {
+            client,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn client(&self) -> &TcpClient {
+        &self.client
+    }
+
+    pub fn client_mut(&mut self) -> &mut TcpClient {
+        &mut self.client
+    }
+
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn consume(&mut self, n: usize) {
+        self.buffer.drain(..n);
+    }
+
+    /// Reads more bytes from the client onto the end of the buffer,
+    /// returning 0 once the client has closed its side.
+    pub async fn fill(&mut self) -> Result<usize> {
+        let mut chunk = [0; 4096];
+        let n = self.client.read(&mut chunk).await?;
+        self.buffer.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    pub fn into_client(self) -> TcpClient {
+        self.client
+    }
+}
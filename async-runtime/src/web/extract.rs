@@ -0,0 +1,471 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::rc::Rc;
+
+use serde::de::value::{Error as ValueError, MapDeserializer};
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, Unexpected, Visitor};
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::body::Body;
+use super::headers::Headers;
+use super::into_response::IntoResponse;
+use super::json::error_response;
+use super::request::Request;
+use super::response::Response;
+use super::url::percent_decode;
+
+/// Something a handler can take as an argument, built from the request
+/// line, headers and route without touching the body, so a handler can
+/// take as many of these as it likes. When one can't be built, the
+/// rejection is sent back instead of calling the handler.
+pub trait FromRequestParts: Sized + 'static {
+    type Rejection: IntoResponse;
+
+    fn from_request_parts(req: &Request) -> LocalBoxedFuture<'_, Result<Self, Self::Rejection>>;
+}
+
+/// Something a handler can take as its last argument, built from the
+/// whole request once every other argument has been extracted. Only one
+/// argument can have the body, and `Handler` only takes these last, so
+/// nothing is left asking for a body that's already been read.
+///
+/// Everything that implements `FromRequestParts` is one of these too.
+pub trait FromRequest: Sized + 'static {
+    type Rejection: IntoResponse;
+
+    fn from_request(req: Request) -> LocalBoxedFuture<'static, Result<Self, Self::Rejection>>;
+}
+
+impl<T: FromRequestParts> FromRequest for T {
+    type Rejection = T::Rejection;
+
+    fn from_request(req: Request) -> LocalBoxedFuture<'static, Result<Self, T::Rejection>> {
+        Box::pin(async move { T::from_request_parts(&req).await })
+    }
+}
+
+/// The whole request, body and all.
+impl FromRequest for Request {
+    type Rejection = Infallible;
+
+    fn from_request(req: Request) -> LocalBoxedFuture<'static, Result<Self, Infallible>> {
+        Box::pin(async move { Ok(req) })
+    }
+}
+
+impl FromRequestParts for Headers {
+    type Rejection = Infallible;
+
+    fn from_request_parts(req: &Request) -> LocalBoxedFuture<'_, Result<Self, Infallible>> {
+        let headers = req.headers.clone();
+        Box::pin(async move { Ok(headers) })
+    }
+}
+
+/// The unread body, for handlers that want to read it themselves.
+impl FromRequest for Body {
+    type Rejection = Infallible;
+
+    fn from_request(mut req: Request) -> LocalBoxedFuture<'static, Result<Self, Infallible>> {
+        let body = std::mem::replace(req.body(), Body::empty());
+        Box::pin(async move { Ok(body) })
+    }
+}
+
+/// Values shared by every handler of a router, one per type.
+#[derive(Clone, Default)]
+pub struct StateMap {
+    values: HashMap<TypeId, Rc<dyn Any>>,
+}
+
+impl StateMap {
+    pub fn insert<T: Clone + 'static>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Rc::new(value));
+    }
+
+    pub fn get<T: Clone + 'static>(&self) -> Option<T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+            .cloned()
+    }
+}
+
+impl fmt::Debug for StateMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StateMap({} values)", self.values.len())
+    }
+}
+
+/// A clone of the state given to the router with `set_state`. Anything
+/// handlers change has to live behind an `Rc<RefCell<_>>` or the like.
+pub struct State<T>(pub T);
+
+impl<T: Clone + 'static> FromRequestParts for State<T> {
+    type Rejection = MissingState;
+
+    fn from_request_parts(req: &Request) -> LocalBoxedFuture<'_, Result<Self, MissingState>> {
+        let state = req
+            .state::<T>()
+            .map(State)
+            .ok_or(MissingState(type_name::<T>()));
+        Box::pin(async move { state })
+    }
+}
+
+/// A handler asked for state the router was never given, which is a bug
+/// in the server rather than the request, so it's a 500.
+#[derive(Debug)]
+pub struct MissingState(&'static str);
+
+impl fmt::Display for MissingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no state of type {} was set on the router", self.0)
+    }
+}
+
+impl IntoResponse for MissingState {
+    fn into_response(self) -> Response {
+        error_response(500, self.to_string())
+    }
+}
+
+/// The parameters captured by the route, decoded and deserialized. A
+/// struct takes them by name, anything else needs a route with exactly
+/// one, as in `Path<u64>` for `/todos/{id}`.
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> Path<T> {
+    pub fn from_request(req: &Request) -> Result<Self, PathRejection> {
+        let mut params = Vec::new();
+        for (name, value) in &req.params {
+            let value =
+                percent_decode(value).ok_or_else(|| PathRejection::Malformed(value.clone()))?;
+            params.push((name.clone(), value));
+        }
+        match T::deserialize(Params(&params)) {
+            Ok(value) => Ok(Path(value)),
+            Err(e) => Err(PathRejection::Invalid(e.to_string())),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequestParts for Path<T> {
+    type Rejection = PathRejection;
+
+    fn from_request_parts(req: &Request) -> LocalBoxedFuture<'_, Result<Self, PathRejection>> {
+        let path = Self::from_request(req);
+        Box::pin(async move { path })
+    }
+}
+
+/// Why the route's parameters couldn't be read. Either way it's a 400,
+/// with a JSON body describing the error.
+#[derive(Debug)]
+pub enum PathRejection {
+    // the parameter with a bad escape
+    Malformed(String),
+    // parameters that don't fit what the handler asked for
+    Invalid(String),
+}
+
+impl PathRejection {
+    pub fn status(&self) -> i32 {
+        400
+    }
+}
+
+impl fmt::Display for PathRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathRejection::Malformed(param) => write!(f, "invalid escape in path: {}", param),
+            PathRejection::Invalid(e) => write!(f, "invalid path parameters: {}", e),
+        }
+    }
+}
+
+impl IntoResponse for PathRejection {
+    fn into_response(self) -> Response {
+        error_response(self.status(), self.to_string())
+    }
+}
+
+// the decoded parameters as a map, or as the value of the only one
+struct Params<'de>(&'de [(String, String)]);
+
+impl<'de> Params<'de> {
+    fn single(self) -> Result<Param<'de>, ValueError> {
+        match self.0 {
+            [(_, value)] => Ok(Param(value)),
+            params => Err(de::Error::custom(format!(
+                "expected a single path parameter, the route has {}",
+                params.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+            self.single()?.$method(visitor)
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Params<'de> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        let params = self
+            .0
+            .iter()
+            .map(|(name, value)| (name.as_str(), Param(value)));
+        visitor.visit_map(MapDeserializer::new(params))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_option
+    }
+
+    serde::forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+// one decoded parameter, parsed into whatever type is asked of it
+struct Param<'de>(&'de str);
+
+macro_rules! parse_param {
+    ($($method:ident => $visit:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+            match self.0.parse() {
+                Ok(value) => visitor.$visit(value),
+                Err(_) => Err(de::Error::invalid_value(Unexpected::Str(self.0), &visitor)),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Param<'de> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    parse_param! {
+        deserialize_bool => visit_bool
+        deserialize_i8 => visit_i8
+        deserialize_i16 => visit_i16
+        deserialize_i32 => visit_i32
+        deserialize_i64 => visit_i64
+        deserialize_u8 => visit_u8
+        deserialize_u16 => visit_u16
+        deserialize_u32 => visit_u32
+        deserialize_u64 => visit_u64
+        deserialize_f32 => visit_f32
+        deserialize_f64 => visit_f64
+        deserialize_char => visit_char
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for Param<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// An async function that takes extractors as its arguments, up to eight
+/// of them, and returns something that can be turned into a response.
+/// `Router::insert` takes any of these. Every argument but the last has
+/// to be `FromRequestParts`, so a handler taking the body anywhere else
+/// doesn't compile.
+pub trait Handler<Args>: 'static {
+    fn call(&self, req: Request) -> LocalBoxedFuture<'_, Response>;
+}
+
+impl<F, Fut, R> Handler<()> for F
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = R> + 'static,
+    R: IntoResponse,
+{
+    fn call(&self, _req: Request) -> LocalBoxedFuture<'_, Response> {
+        Box::pin(async move { self().await.into_response() })
+    }
+}
+
+macro_rules! impl_handler {
+    ($($arg:ident)*; $last:ident) => {
+        #[allow(non_snake_case)]
+        impl<F, Fut, R, $($arg,)* $last> Handler<($($arg,)* $last,)> for F
+        where
+            F: Fn($($arg,)* $last) -> Fut + 'static,
+            Fut: Future<Output = R> + 'static,
+            R: IntoResponse,
+            $($arg: FromRequestParts,)*
+            $last: FromRequest,
+        {
+            fn call(&self, req: Request) -> LocalBoxedFuture<'_, Response> {
+                Box::pin(async move {
+                    $(
+                        let $arg = match $arg::from_request_parts(&req).await {
+                            Ok(value) => value,
+                            Err(rejection) => return rejection.into_response(),
+                        };
+                    )*
+                    let $last = match $last::from_request(req).await {
+                        Ok(value) => value,
+                        Err(rejection) => return rejection.into_response(),
+                    };
+                    self($($arg,)* $last).await.into_response()
+                })
+            }
+        }
+    };
+}
+
+impl_handler!(; T1);
+impl_handler!(T1; T2);
+impl_handler!(T1 T2; T3);
+impl_handler!(T1 T2 T3; T4);
+impl_handler!(T1 T2 T3 T4; T5);
+impl_handler!(T1 T2 T3 T4 T5; T6);
+impl_handler!(T1 T2 T3 T4 T5 T6; T7);
+impl_handler!(T1 T2 T3 T4 T5 T6 T7; T8);
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::web::query::Query;
+    use crate::web::request::Method;
+    use crate::web::response::ResponseBody;
+    use crate::web::router::Router;
+    use crate::web::test_util::{request, run};
+
+    #[derive(Deserialize)]
+    struct Item {
+        list: String,
+        id: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct Page {
+        page: Option<u32>,
+    }
+
+    #[test]
+    fn test_extractors() {
+        let mut router = Router::new();
+        router.set_state(String::from("shop"));
+        router.insert(Method::GET, "/", || async { "index" });
+        router.insert(
+            Method::GET,
+            "/lists/{list}/items/{id}",
+            |State(name): State<String>, Path(item): Path<Item>, Query(page): Query<Page>| async move {
+                format!("{} {} {} {:?}", name, item.list, item.id, page.page)
+            },
+        );
+        router.insert(
+            Method::GET,
+            "/users/{id}",
+            |Path(id): Path<u64>| async move { id.to_string() },
+        );
+        router.insert(Method::GET, "/count", |State(n): State<u8>| async move {
+            n.to_string()
+        });
+        router.insert(
+            Method::GET,
+            "/raw",
+            |req: Request| async move { req.target },
+        );
+        router.insert(
+            Method::GET,
+            "/both",
+            |Query(page): Query<Page>, req: Request| async move {
+                format!("{:?} {}", page.page, req.target)
+            },
+        );
+
+        let router = Rc::new(router);
+        let targets = [
+            "/",
+            "/lists/to%20do/items/7?page=2",
+            "/users/42",
+            "/users/me",
+            "/users/%ff",
+            "/lists/a/items/1?page=x",
+            "/count",
+            "/raw?x=1",
+            "/both?page=3",
+        ];
+        let results: Vec<(i32, String)> = targets
+            .into_iter()
+            .map(|target| {
+                let mut res = run(&router, request(Method::GET, target, &[], b""));
+                let body = match std::mem::take(&mut res.body) {
+                    ResponseBody::Bytes(body) => body,
+                    _ => Vec::new(),
+                };
+                (res.status, String::from_utf8(body).unwrap())
+            })
+            .collect();
+
+        assert_eq!(results[0], (200, String::from("index")));
+        assert_eq!(results[1], (200, String::from("shop to do 7 Some(2)")));
+        assert_eq!(results[2], (200, String::from("42")));
+        assert_eq!(results[3].0, 400);
+        assert!(results[3].1.contains("expected u64"));
+        assert_eq!(results[4].0, 400);
+        assert_eq!(results[5].0, 400);
+        assert_eq!(results[6].0, 500);
+        assert_eq!(results[7], (200, String::from("/raw?x=1")));
+        assert_eq!(results[8], (200, String::from("Some(3) /both?page=3")));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+use std::io::{ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::vec;
+
+use crate::async_io::stream::Stream;
+use crate::async_net::client::TcpClient;
+
+use super::cache::validators;
+use super::into_response::IntoResponse;
+use super::mime;
+use super::response::{BodyStream, Chunk, Response, ResponseBody};
+
+// how much of a file is read into memory at a time while it's sent
+const READ_SIZE: usize = 64 * 1024;
+
+/// A file sent as the whole response body, typed by its extension unless
+/// given a MIME type. It carries `ETag` and `Last-Modified` validators so
+/// `ConditionalGet` can answer repeat requests with a 304.
+pub struct File {
+    path: PathBuf,
+    mime_type: Option<String>,
+    cache_control: Option<String>,
+}
+
+impl File {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        File {
+            path: path.into(),
+            mime_type: None,
+            cache_control: None,
+        }
+    }
+
+    pub fn with_mime_type(mut self, mime_type: &str) -> Self {
+        self.mime_type = Some(String::from(mime_type));
+        self
+    }
+
+    pub fn with_cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = Some(String::from(cache_control));
+        self
+    }
+}
+
+impl IntoResponse for File {
+    fn into_response(self) -> Response {
+        let open = fs::File::open(&self.path).and_then(|file| {
+            let metadata = file.metadata()?;
+            Ok((file, metadata))
+        });
+        let (file, metadata) = match open {
+            Ok(open) => open,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return (404, "404 NOT FOUND\n").into_response()
+            }
+            Err(e) => return e.into_response(),
+        };
+        let mime_type = match &self.mime_type {
+            Some(mime_type) => mime_type.as_str(),
+            None => mime::from_path(&self.path.to_string_lossy()),
+        };
+        let (etag, last_modified) = validators(&metadata);
+        let mut res = Response::builder()
+            .content_type(&mime::content_type(mime_type))
+            .header("etag", &etag)
+            .header("accept-ranges", "bytes")
+            .body(ResponseBody::File(FileBody::new(file, metadata.len())));
+        if let Some(last_modified) = last_modified {
+            res.set_header("last-modified", &last_modified);
+        }
+        if let Some(cache_control) = &self.cache_control {
+            res.set_header("cache-control", cache_control);
+        }
+        res
+    }
+}
+
+/// Part of a `FileBody`, either bytes of its own or an inclusive range of
+/// the file's bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    Bytes(Vec<u8>),
+    Range(u64, u64),
+}
+
+/// A response body read off an open file as it's written, so only the
+/// parts being sent are ever read, and never all at once.
+#[derive(Debug)]
+pub struct FileBody {
+    file: fs::File,
+    segments: Vec<Segment>,
+}
+
+impl FileBody {
+    /// The whole of `file`, which is `len` bytes long.
+    pub fn new(file: fs::File, len: u64) -> Self {
+        let segments = match len {
+            0 => Vec::new(),
+            len => vec![Segment::Range(0, len - 1)],
+        };
+        FileBody { file, segments }
+    }
+
+    /// Sends `segments` in place of what the body held before, as when
+    /// answering a range request.
+    pub fn with_segments(mut self, segments: Vec<Segment>) -> Self {
+        self.segments = segments;
+        self
+    }
+
+    pub fn len(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Bytes(bytes) => bytes.len() as u64,
+                Segment::Range(first, last) => last - first + 1,
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads everything the body holds into memory.
+    pub fn read_all(&mut self) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.len() as usize);
+        for segment in &self.segments {
+            match segment {
+                Segment::Bytes(bytes) => out.extend_from_slice(bytes),
+                &Segment::Range(first, last) => {
+                    self.file.seek(SeekFrom::Start(first))?;
+                    let start = out.len();
+                    out.resize(start + (last - first + 1) as usize, 0);
+                    self.file.read_exact(&mut out[start..])?;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// The body as a stream, read a piece at a time as it's polled, for
+    /// middleware that transforms the body on its way out.
+    pub fn into_stream(self) -> BodyStream {
+        Box::pin(FileStream {
+            file: self.file,
+            segments: self.segments.into_iter(),
+            range: None,
+        })
+    }
+
+    pub async fn write(&mut self, client: &mut TcpClient) -> Result<()> {
+        let mut buf = vec![0; READ_SIZE];
+        for segment in &self.segments {
+            match segment {
+                Segment::Bytes(bytes) => client.write_all(bytes).await?,
+                &Segment::Range(first, last) => {
+                    self.file.seek(SeekFrom::Start(first))?;
+                    let mut remaining = last - first + 1;
+                    while remaining > 0 {
+                        let n = remaining.min(READ_SIZE as u64) as usize;
+                        self.file.read_exact(&mut buf[..n])?;
+                        client.write_all(&buf[..n]).await?;
+                        remaining -= n as u64;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct FileStream {
+    file: fs::File,
+    segments: vec::IntoIter<Segment>,
+    // where the range being read is up to, and how much of it is left
+    range: Option<(u64, u64)>,
+}
+
+impl Stream for FileStream {
+    type Item = Result<Chunk>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some((pos, remaining)) = this.range {
+                let n = remaining.min(READ_SIZE as u64);
+                let mut buf = vec![0; n as usize];
+                let read = this.file.seek(SeekFrom::Start(pos));
+                if let Err(e) = read.and_then(|_| this.file.read_exact(&mut buf)) {
+                    // nothing after a failed read can be trusted
+                    this.range = None;
+                    this.segments = Vec::new().into_iter();
+                    return Poll::Ready(Some(Err(e)));
+                }
+                this.range = (remaining > n).then_some((pos + n, remaining - n));
+                return Poll::Ready(Some(Ok(Chunk::Data(buf))));
+            }
+            match this.segments.next() {
+                Some(Segment::Bytes(bytes)) => return Poll::Ready(Some(Ok(Chunk::Data(bytes)))),
+                Some(Segment::Range(first, last)) => this.range = Some((first, last - first + 1)),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
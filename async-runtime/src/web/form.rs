@@ -0,0 +1,492 @@
+use std::fmt;
+use std::io;
+
+use serde::de::DeserializeOwned;
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::body::{Body, BodyKind};
+use super::extract::FromRequest;
+use super::headers::Headers;
+use super::into_response::{error_status, IntoResponse};
+use super::json::error_response;
+use super::parser::parse_header_line;
+use super::request::Request;
+use super::response::Response;
+
+// the headers of one part, which are all that's ever held in memory at once
+const MAX_PART_HEAD: usize = 8 * 1024;
+// bytes pulled off the body at a time
+const READ_SIZE: usize = 8 * 1024;
+
+/// A value parsed from an `application/x-www-form-urlencoded` body.
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> Form<T> {
+    pub const DEFAULT_LIMIT: u64 = 64 * 1024;
+
+    /// Reads the body of `req` as a urlencoded form, as long as it's sent
+    /// as one and fits within `DEFAULT_LIMIT`.
+    pub async fn from_request(req: &mut Request) -> Result<Self, FormRejection> {
+        Self::from_request_with_limit(req, Self::DEFAULT_LIMIT).await
+    }
+
+    pub async fn from_request_with_limit(
+        req: &mut Request,
+        limit: u64,
+    ) -> Result<Self, FormRejection> {
+        let content_type = req.header("content-type").unwrap_or_default();
+        if mime_type(content_type) != "application/x-www-form-urlencoded" {
+            return Err(FormRejection::UnsupportedMediaType);
+        }
+        if let BodyKind::Length(len) = req.body_kind() {
+            if len > limit {
+                return Err(FormRejection::PayloadTooLarge);
+            }
+        }
+
+        let mut body = Vec::new();
+        if let Err(e) = req.body().read_to_end(&mut body, limit).await {
+            return match e.kind() {
+                io::ErrorKind::FileTooLarge => Err(FormRejection::PayloadTooLarge),
+                _ => Err(FormRejection::Body(e)),
+            };
+        }
+        match serde_urlencoded::from_bytes(&body) {
+            Ok(value) => Ok(Form(value)),
+            Err(e) => Err(FormRejection::Data(e.to_string())),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Form<T> {
+    type Rejection = FormRejection;
+
+    fn from_request(mut req: Request) -> LocalBoxedFuture<'static, Result<Self, FormRejection>> {
+        Box::pin(async move { Self::from_request_with_limit(&mut req, Self::DEFAULT_LIMIT).await })
+    }
+}
+
+fn mime_type(content_type: &str) -> String {
+    let mime_type = content_type.split(';').next().unwrap_or_default();
+    mime_type.trim().to_ascii_lowercase()
+}
+
+// the value of `name` among the `; name=value` parameters of a header,
+// unquoting it if need be
+fn header_param(value: &str, name: &str) -> Option<String> {
+    let mut params = value.split(';').skip(1);
+    let value = params.find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })?;
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(quoted) => Some(quoted.replace("\\\"", "\"").replace("\\\\", "\\")),
+        None => Some(String::from(value)),
+    }
+}
+
+/// Why a form couldn't be read from a request. As a response it's the
+/// status along with a JSON body describing the error.
+#[derive(Debug)]
+pub enum FormRejection {
+    UnsupportedMediaType,
+    PayloadTooLarge,
+    // reading the body failed, or it timed out
+    Body(io::Error),
+    // a multipart body that doesn't follow the format
+    Malformed(&'static str),
+    // fields that don't fit what the handler asked for
+    Data(String),
+}
+
+impl FormRejection {
+    pub fn status(&self) -> i32 {
+        match self {
+            FormRejection::UnsupportedMediaType => 415,
+            FormRejection::PayloadTooLarge => 413,
+            FormRejection::Body(e) => error_status(e),
+            FormRejection::Malformed(_) => 400,
+            FormRejection::Data(_) => 422,
+        }
+    }
+}
+
+impl fmt::Display for FormRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormRejection::UnsupportedMediaType => write!(f, "expected a form body"),
+            FormRejection::PayloadTooLarge => write!(f, "request body is too large"),
+            FormRejection::Body(e) => write!(f, "failed to read the request body: {}", e),
+            FormRejection::Malformed(reason) => write!(f, "invalid multipart body: {}", reason),
+            FormRejection::Data(e) => write!(f, "unexpected form fields: {}", e),
+        }
+    }
+}
+
+impl IntoResponse for FormRejection {
+    fn into_response(self) -> Response {
+        error_response(self.status(), self.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    // before the first boundary
+    Preamble,
+    // right after a boundary, which either ends the body or starts a part
+    Boundary,
+    Headers,
+    Data,
+    Done,
+}
+
+/// A `multipart/form-data` body read one part at a time, so uploads are
+/// never held in memory whole. The body is also capped by its route's
+/// limit, `Limits::max_body_size` unless the route sets `body_limit`, so
+/// routes taking uploads need to raise it to reach `max_total_size`.
+pub struct Multipart {
+    body: Body,
+    // `\r\n--boundary`, the line break belongs to the delimiter
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    state: State,
+    read: u64,
+    max_part_size: u64,
+    max_total_size: u64,
+}
+
+impl Multipart {
+    /// Takes the body of `req`, which has to be sent as multipart form data.
+    pub fn from_request(req: &mut Request) -> Result<Self, FormRejection> {
+        let content_type = req.header("content-type").unwrap_or_default();
+        if mime_type(content_type) != "multipart/form-data" {
+            return Err(FormRejection::UnsupportedMediaType);
+        }
+        let boundary = header_param(content_type, "boundary")
+            .filter(|boundary| (1..=70).contains(&boundary.len()))
+            .ok_or(FormRejection::Malformed("missing boundary"))?;
+
+        let body = std::mem::replace(req.body(), Body::empty());
+        Ok(Multipart {
+            body,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // so the first boundary looks like every other one
+            buffer: b"\r\n".to_vec(),
+            state: State::Preamble,
+            read: 0,
+            max_part_size: 8 * 1024 * 1024,
+            max_total_size: 32 * 1024 * 1024,
+        })
+    }
+
+    /// The most a single field or file may hold.
+    pub fn max_part_size(mut self, max_part_size: u64) -> Self {
+        self.max_part_size = max_part_size;
+        self
+    }
+
+    /// The most the whole body may hold, parts and their headers together.
+    pub fn max_total_size(mut self, max_total_size: u64) -> Self {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    /// The next field or file, skipping whatever was left unread of the
+    /// last one, or `None` after the final boundary.
+    pub async fn next_part(&mut self) -> Result<Option<Part<'_>>, FormRejection> {
+        loop {
+            match self.state {
+                State::Preamble => match find(&self.buffer, &self.delimiter) {
+                    Some(at) => {
+                        self.buffer.drain(..at + self.delimiter.len());
+                        self.state = State::Boundary;
+                    }
+                    None => {
+                        let keep = self.buffer.len().min(self.delimiter.len());
+                        self.buffer.drain(..self.buffer.len() - keep);
+                        self.fill().await?;
+                    }
+                },
+                State::Data => while self.read_data().await?.is_some() {},
+                State::Boundary => {
+                    if self.buffer.starts_with(b"--") {
+                        self.state = State::Done;
+                    } else if let Some(end) = find(&self.buffer, b"\r\n") {
+                        // only transport padding may follow a boundary on its line
+                        if self.buffer[..end].iter().any(|b| *b != b' ' && *b != b'\t') {
+                            return Err(FormRejection::Malformed("text after a boundary"));
+                        }
+                        self.buffer.drain(..end + 2);
+                        self.state = State::Headers;
+                    } else if self.buffer.len() > MAX_PART_HEAD {
+                        return Err(FormRejection::Malformed("text after a boundary"));
+                    } else {
+                        self.fill().await?;
+                    }
+                }
+                State::Headers => {
+                    let head = if self.buffer.starts_with(b"\r\n") {
+                        Some(0)
+                    } else {
+                        find(&self.buffer, b"\r\n\r\n").map(|end| end + 2)
+                    };
+                    match head {
+                        Some(end) => {
+                            let (headers, name, filename) = self.parse_head(end)?;
+                            self.buffer.drain(..end + 2);
+                            self.state = State::Data;
+                            return Ok(Some(Part {
+                                multipart: self,
+                                headers,
+                                name,
+                                filename,
+                                size: 0,
+                            }));
+                        }
+                        None if self.buffer.len() > MAX_PART_HEAD => {
+                            return Err(FormRejection::Malformed("part headers are too large"));
+                        }
+                        None => self.fill().await?,
+                    }
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    // the headers of a part along with its field name and file name
+    fn parse_head(&self, end: usize) -> Result<(Headers, String, Option<String>), FormRejection> {
+        let mut headers = Headers::new();
+        for line in self.buffer[..end].split(|b| *b == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = parse_header_line(line)
+                .map_err(|_| FormRejection::Malformed("invalid part header"))?;
+            headers.append(&name, &value);
+        }
+
+        let disposition = headers
+            .get("content-disposition")
+            .filter(|value| mime_type(value) == "form-data")
+            .ok_or(FormRejection::Malformed(
+                "part without form-data disposition",
+            ))?;
+        let name = header_param(disposition, "name")
+            .ok_or(FormRejection::Malformed("part without a name"))?;
+        let filename = header_param(disposition, "filename");
+        Ok((headers, name, filename))
+    }
+
+    // the next piece of the current part's data, `None` once it's done
+    async fn read_data(&mut self) -> Result<Option<Vec<u8>>, FormRejection> {
+        while self.state == State::Data {
+            if let Some(at) = find(&self.buffer, &self.delimiter) {
+                let data: Vec<u8> = self.buffer.drain(..at).collect();
+                self.buffer.drain(..self.delimiter.len());
+                self.state = State::Boundary;
+                if !data.is_empty() {
+                    return Ok(Some(data));
+                }
+                break;
+            }
+            // hold back what could be the start of a delimiter
+            let safe = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 {
+                return Ok(Some(self.buffer.drain(..safe).collect()));
+            }
+            self.fill().await?;
+        }
+        Ok(None)
+    }
+
+    async fn fill(&mut self) -> Result<(), FormRejection> {
+        let mut buf = [0; READ_SIZE];
+        let n = self
+            .body
+            .read(&mut buf)
+            .await
+            .map_err(FormRejection::Body)?;
+        if n == 0 {
+            return Err(FormRejection::Malformed(
+                "body ended before the last boundary",
+            ));
+        }
+        self.read += n as u64;
+        if self.read > self.max_total_size {
+            return Err(FormRejection::PayloadTooLarge);
+        }
+        self.buffer.extend_from_slice(&buf[..n]);
+        Ok(())
+    }
+}
+
+impl FromRequest for Multipart {
+    type Rejection = FormRejection;
+
+    fn from_request(mut req: Request) -> LocalBoxedFuture<'static, Result<Self, FormRejection>> {
+        let form = Self::from_request(&mut req);
+        Box::pin(async move { form })
+    }
+}
+
+/// A field or file of a multipart form, its data read piece by piece.
+pub struct Part<'m> {
+    multipart: &'m mut Multipart,
+    headers: Headers,
+    name: String,
+    filename: Option<String>,
+    size: u64,
+}
+
+impl Part<'_> {
+    /// The name of the form field.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of an uploaded file on the client, if the part is one.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get("content-type")
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The next piece of data, `None` once the part has been read.
+    pub async fn chunk(&mut self) -> Result<Option<Vec<u8>>, FormRejection> {
+        let data = self.multipart.read_data().await?;
+        if let Some(data) = &data {
+            self.size += data.len() as u64;
+            if self.size > self.multipart.max_part_size {
+                return Err(FormRejection::PayloadTooLarge);
+            }
+        }
+        Ok(data)
+    }
+
+    /// Reads the rest of the part into memory.
+    pub async fn bytes(mut self) -> Result<Vec<u8>, FormRejection> {
+        let mut bytes = Vec::new();
+        while let Some(data) = self.chunk().await? {
+            bytes.extend_from_slice(&data);
+        }
+        Ok(bytes)
+    }
+
+    pub async fn text(self) -> Result<String, FormRejection> {
+        String::from_utf8(self.bytes().await?)
+            .map_err(|_| FormRejection::Data(String::from("field isn't valid UTF-8")))
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::async_io::executor;
+    use crate::web::request::Method;
+    use crate::web::test_util::request;
+
+    #[derive(Deserialize)]
+    struct Todo {
+        title: String,
+        done: bool,
+    }
+
+    #[test]
+    fn test_urlencoded_form() {
+        executor::block_on(async {
+            let urlencoded = [("content-type", "application/x-www-form-urlencoded")];
+            let mut req = request(
+                Method::POST,
+                "/",
+                &urlencoded,
+                b"title=buy+milk%21&done=true",
+            );
+            let Form(todo) = Form::<Todo>::from_request(&mut req).await.unwrap();
+            assert_eq!(todo.title, "buy milk!");
+            assert!(todo.done);
+
+            let mut req = request(Method::POST, "/", &urlencoded, b"title=x&done=maybe");
+            let rejection = Form::<Todo>::from_request(&mut req).await.err().unwrap();
+            assert_eq!(rejection.status(), 422);
+            let mut req = request(
+                Method::POST,
+                "/",
+                &[("content-type", "text/plain")],
+                b"title=x&done=true",
+            );
+            let rejection = Form::<Todo>::from_request(&mut req).await.err().unwrap();
+            assert_eq!(rejection.status(), 415);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_multipart() {
+        // big enough that the file comes in over several reads
+        let file = "0123456789".repeat(3000);
+        let body = format!(
+            "preamble\r\n--xyz\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+             hello\r\n--xyz\r\n\
+             Content-Disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             {}\r\n--xyz\r\n\
+             Content-Disposition: form-data; name=\"skipped\"\r\n\r\n\
+             never read\r\n--xyz--\r\nepilogue",
+            file
+        );
+        let headers = [("content-type", "multipart/form-data; boundary=xyz")];
+        let file_len = file.len();
+
+        executor::block_on(async move {
+            let mut req = request(Method::POST, "/", &headers, body.as_bytes());
+            let mut form = Multipart::from_request(&mut req).unwrap();
+
+            let part = form.next_part().await.unwrap().unwrap();
+            assert_eq!(part.name(), "title");
+            assert_eq!(part.filename(), None);
+            assert_eq!(part.text().await.unwrap(), "hello");
+
+            let mut part = form.next_part().await.unwrap().unwrap();
+            assert_eq!(part.filename(), Some("a \"b\".txt"));
+            assert_eq!(part.content_type(), Some("text/plain"));
+            let mut chunks = 0;
+            let mut size = 0;
+            while let Some(data) = part.chunk().await.unwrap() {
+                chunks += 1;
+                size += data.len();
+            }
+            assert!(chunks > 1);
+            assert_eq!(size, file_len);
+
+            assert_eq!(form.next_part().await.unwrap().unwrap().name(), "skipped");
+            assert!(form.next_part().await.unwrap().is_none());
+
+            let mut req = request(Method::POST, "/", &headers, body.as_bytes());
+            let mut form = Multipart::from_request(&mut req)
+                .unwrap()
+                .max_part_size(1024);
+            form.next_part().await.unwrap();
+            let part = form.next_part().await.unwrap().unwrap();
+            assert_eq!(part.bytes().await.err().unwrap().status(), 413);
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use std::convert::Infallible;
+use std::io::{self, ErrorKind};
+
+use super::response::{status, status_code, Response, ResponseBody};
+
+/// Anything a handler can return. The router turns it into a `Response`
+/// and writes it out.
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for Infallible {
+    fn into_response(self) -> Response {
+        match self {}
+    }
+}
+
+impl IntoResponse for () {
+    fn into_response(self) -> Response {
+        Response::new(200, ())
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        String::from(self).into_response()
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::builder()
+            .content_type("text/plain; charset=UTF-8")
+            .body(self)
+    }
+}
+
+impl IntoResponse for &'static [u8] {
+    fn into_response(self) -> Response {
+        self.to_vec().into_response()
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        Response::builder()
+            .content_type("application/octet-stream")
+            .body(self)
+    }
+}
+
+impl IntoResponse for ResponseBody {
+    fn into_response(self) -> Response {
+        Response::new(200, self)
+    }
+}
+
+/// Overrides the status of the response, e.g. `(201, "created")`.
+impl<T: IntoResponse> IntoResponse for (i32, T) {
+    fn into_response(self) -> Response {
+        let mut res = self.1.into_response();
+        res.status = self.0;
+        res
+    }
+}
+
+/// Adds headers to the response, e.g. `(201, [("location", "/todo/1")], "")`.
+impl<T: IntoResponse, const N: usize> IntoResponse for (i32, [(&str, &str); N], T) {
+    fn into_response(self) -> Response {
+        let mut res = (self.0, self.2).into_response();
+        for (name, value) in self.1 {
+            res.set_header(name, value);
+        }
+        res
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(res) => res.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+}
+
+/// The status to answer with when `e` stopped a request from being served.
+pub fn error_status(e: &io::Error) -> i32 {
+    match e.kind() {
+        ErrorKind::TimedOut => 408,
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof => 400,
+        ErrorKind::FileTooLarge => 413,
+        ErrorKind::NotFound => 404,
+        ErrorKind::PermissionDenied => 403,
+        _ => 500,
+    }
+}
+
+/// Errors a handler passes up with `?`, mostly from reading the body.
+impl IntoResponse for io::Error {
+    fn into_response(self) -> Response {
+        let code = status_code(error_status(&self));
+        (code, format!("{} {}\n", code, status(code))).into_response()
+    }
+}
+
+pub struct Html<T>(pub T);
+
+impl<T: Into<ResponseBody>> IntoResponse for Html<T> {
+    fn into_response(self) -> Response {
+        Response::builder()
+            .content_type("text/html; charset=UTF-8")
+            .body(self.0)
+    }
+}
+
+pub struct Redirect {
+    status: i32,
+    location: String,
+}
+
+impl Redirect {
+    /// `303 See Other`, the follow up request is always a GET.
+    pub fn to(location: &str) -> Self {
+        Redirect::with_status(303, location)
+    }
+
+    /// `307 Temporary Redirect`, the method and body are kept.
+    pub fn temporary(location: &str) -> Self {
+        Redirect::with_status(307, location)
+    }
+
+    /// `308 Permanent Redirect`, the method and body are kept.
+    pub fn permanent(location: &str) -> Self {
+        Redirect::with_status(308, location)
+    }
+
+    fn with_status(status: i32, location: &str) -> Self {
+        Redirect {
+            status,
+            location: String::from(location),
+        }
+    }
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> Response {
+        Response::builder()
+            .status(self.status)
+            .header("location", &self.location)
+            .body(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_response() {
+        let res = (201, [("location", "/todo/1")], "created").into_response();
+        assert_eq!(res.status, 201);
+        assert_eq!(res.header("location"), Some("/todo/1"));
+        assert_eq!(
+            res.header("content-type"),
+            Some("text/plain; charset=UTF-8")
+        );
+        assert_eq!(res.body.len(), 7);
+
+        let res: Result<String, io::Error> = Err(ErrorKind::TimedOut.into());
+        assert_eq!(res.into_response().status, 408);
+
+        let res = Redirect::permanent("/new").into_response();
+        assert_eq!(res.status, 308);
+        assert!(res.body.is_empty());
+    }
+}
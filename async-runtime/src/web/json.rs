@@ -0,0 +1,191 @@
+use std::fmt;
+use std::io;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::body::BodyKind;
+use super::extract::FromRequest;
+use super::into_response::{error_status, IntoResponse};
+use super::request::Request;
+use super::response::{status, Response};
+
+/// A value serialized as the JSON body of a response, or parsed from the
+/// JSON body of a request with `Json::from_request`.
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> Json<T> {
+    /// Bodies larger than this are turned away unless a handler asks for
+    /// a different limit.
+    pub const DEFAULT_LIMIT: u64 = 1024 * 1024;
+
+    /// Reads the body of `req` as JSON, as long as it's sent as JSON and
+    /// fits within `DEFAULT_LIMIT`.
+    pub async fn from_request(req: &mut Request) -> Result<Self, JsonRejection> {
+        Self::from_request_with_limit(req, Self::DEFAULT_LIMIT).await
+    }
+
+    pub async fn from_request_with_limit(
+        req: &mut Request,
+        limit: u64,
+    ) -> Result<Self, JsonRejection> {
+        if !req.header("content-type").is_some_and(is_json) {
+            return Err(JsonRejection::UnsupportedMediaType);
+        }
+        // no need to read a body that's announced as too big
+        if let BodyKind::Length(len) = req.body_kind() {
+            if len > limit {
+                return Err(JsonRejection::PayloadTooLarge);
+            }
+        }
+
+        let mut body = Vec::new();
+        if let Err(e) = req.body().read_to_end(&mut body, limit).await {
+            return match e.kind() {
+                io::ErrorKind::FileTooLarge => Err(JsonRejection::PayloadTooLarge),
+                _ => Err(JsonRejection::Body(e)),
+            };
+        }
+        match serde_json::from_slice(&body) {
+            Ok(value) => Ok(Json(value)),
+            // well formed JSON that doesn't fit `T` is a 422, anything else a 400
+            Err(e) if e.is_data() => Err(JsonRejection::Data(e)),
+            Err(e) => Err(JsonRejection::Syntax(e)),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Json<T> {
+    type Rejection = JsonRejection;
+
+    fn from_request(mut req: Request) -> LocalBoxedFuture<'static, Result<Self, JsonRejection>> {
+        Box::pin(async move { Self::from_request_with_limit(&mut req, Self::DEFAULT_LIMIT).await })
+    }
+}
+
+// `application/json`, or a type built on it like `application/problem+json`
+fn is_json(content_type: &str) -> bool {
+    let mime_type = content_type.split(';').next().unwrap_or_default().trim();
+    let mime_type = mime_type.to_ascii_lowercase();
+    mime_type == "application/json"
+        || (mime_type.starts_with("application/") && mime_type.ends_with("+json"))
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        match serde_json::to_vec(&self.0) {
+            Ok(body) => Response::builder()
+                .content_type("application/json")
+                .body(body),
+            Err(e) => (500, format!("500 INTERNAL SERVER ERROR\n{}\n", e)).into_response(),
+        }
+    }
+}
+
+/// Why a request body couldn't be read as JSON. As a response it's the
+/// status along with a JSON body describing the error.
+#[derive(Debug)]
+pub enum JsonRejection {
+    UnsupportedMediaType,
+    PayloadTooLarge,
+    // reading the body failed, or it timed out
+    Body(io::Error),
+    Syntax(serde_json::Error),
+    Data(serde_json::Error),
+}
+
+impl JsonRejection {
+    pub fn status(&self) -> i32 {
+        match self {
+            JsonRejection::UnsupportedMediaType => 415,
+            JsonRejection::PayloadTooLarge => 413,
+            JsonRejection::Body(e) => error_status(e),
+            JsonRejection::Syntax(_) => 400,
+            JsonRejection::Data(_) => 422,
+        }
+    }
+}
+
+impl fmt::Display for JsonRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonRejection::UnsupportedMediaType => {
+                write!(f, "expected a body with content-type application/json")
+            }
+            JsonRejection::PayloadTooLarge => write!(f, "request body is too large"),
+            JsonRejection::Body(e) => write!(f, "failed to read the request body: {}", e),
+            JsonRejection::Syntax(e) => write!(f, "invalid JSON: {}", e),
+            JsonRejection::Data(e) => write!(f, "unexpected JSON: {}", e),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    status: i32,
+    error: &'static str,
+    message: String,
+}
+
+/// The JSON error body a rejected request gets, with `message` describing
+/// what was wrong with it.
+pub fn error_response(code: i32, message: String) -> Response {
+    let body = ErrorBody {
+        status: code,
+        error: status(code),
+        message,
+    };
+    (code, Json(body)).into_response()
+}
+
+impl IntoResponse for JsonRejection {
+    fn into_response(self) -> Response {
+        error_response(self.status(), self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::async_io::executor;
+    use crate::web::request::Method;
+    use crate::web::test_util::request;
+
+    #[derive(Debug, Deserialize)]
+    struct Todo {
+        title: String,
+    }
+
+    async fn status(content_type: &str, body: &str) -> i32 {
+        let headers = [("content-type", content_type)];
+        let mut req = request(Method::POST, "/", &headers, body.as_bytes());
+        match Json::<Todo>::from_request_with_limit(&mut req, 64).await {
+            Ok(_) => 200,
+            Err(e) => e.status(),
+        }
+    }
+
+    #[test]
+    fn test_json_rejections() {
+        executor::block_on(async {
+            let headers = [("content-type", "application/json; charset=utf-8")];
+            let mut req = request(Method::POST, "/", &headers, br#"{"title": "a"}"#);
+            let Json(todo) = Json::<Todo>::from_request(&mut req).await.unwrap();
+            assert_eq!(todo.title, "a");
+
+            assert_eq!(
+                status("application/merge-patch+json", "{\"title\": \"b\"}").await,
+                200
+            );
+            assert_eq!(status("text/plain", "{}").await, 415);
+            assert_eq!(status("application/json", "{\"title\": ").await, 400);
+            assert_eq!(status("application/json", "{\"title\": 1}").await, 422);
+            assert_eq!(status("application/json", &"x".repeat(100)).await, 413);
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::rc::Rc;
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::request::Request;
+use super::response::Response;
+
+/// Runs around a handler. Call `next.run(req)` to pass the (possibly
+/// rewritten) request on down the chain and get its response back, or
+/// return a response without calling it to short-circuit.
+pub trait Middleware {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> LocalBoxedFuture<'a, Response>;
+}
+
+pub type Endpoint<'a> = dyn Fn(Request) -> LocalBoxedFuture<'a, Response> + 'a;
+
+/// The rest of the middleware chain, ending with the handler.
+pub struct Next<'a> {
+    middleware: &'a [Rc<dyn Middleware>],
+    endpoint: &'a Endpoint<'a>,
+}
+
+impl<'a> Next<'a> {
+    pub fn new(middleware: &'a [Rc<dyn Middleware>], endpoint: &'a Endpoint<'a>) -> Self {
+        Next {
+            middleware,
+            endpoint,
+        }
+    }
+
+    pub fn run(self, req: Request) -> LocalBoxedFuture<'a, Response> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.call(req, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(req),
+        }
+    }
+}
+
+pub struct FromFn<F>(F);
+
+/// Middleware from a closure, e.g.
+/// `from_fn(|req, next| Box::pin(async move { next.run(req).await }))`.
+pub fn from_fn<F>(f: F) -> FromFn<F>
+where
+    F: for<'a> Fn(Request, Next<'a>) -> LocalBoxedFuture<'a, Response>,
+{
+    FromFn(f)
+}
+
+impl<F> Middleware for FromFn<F>
+where
+    F: for<'a> Fn(Request, Next<'a>) -> LocalBoxedFuture<'a, Response>,
+{
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> LocalBoxedFuture<'a, Response> {
+        (self.0)(req, next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::request::Method;
+    use crate::web::router::Router;
+    use crate::web::test_util::{request, run};
+
+    #[test]
+    fn test_middleware_chain() {
+        let mut router = Router::new();
+        router.wrap(from_fn(|req, next| {
+            Box::pin(async move {
+                let mut res = next.run(req).await;
+                res.set_header("x-global", "1");
+                res
+            })
+        }));
+        router.insert(Method::GET, "/open", |req: Request| async move {
+            String::from(req.header("x-rewritten").unwrap_or("no"))
+        });
+        router
+            .insert(Method::GET, "/rewritten", |req: Request| async move {
+                String::from(req.header("x-rewritten").unwrap_or("no"))
+            })
+            .wrap(from_fn(|mut req, next| {
+                req.headers.insert("x-rewritten", "yes");
+                next.run(req)
+            }));
+
+        let mut admin = router.group("/admin");
+        admin.wrap(from_fn(|req, next| {
+            if req.header("authorization").is_some() {
+                next.run(req)
+            } else {
+                Box::pin(async { Response::new(401, ()) })
+            }
+        }));
+        admin.insert(Method::GET, "/", || async { "dashboard" });
+
+        let router = Rc::new(router);
+        let requests = [
+            request(Method::GET, "/open", &[], b""),
+            request(Method::GET, "/rewritten", &[], b""),
+            request(Method::GET, "/admin", &[], b""),
+            request(Method::GET, "/admin", &[("authorization", "token")], b""),
+            request(Method::GET, "/missing", &[], b""),
+        ];
+        let results: Vec<Response> = requests.into_iter().map(|req| run(&router, req)).collect();
+
+        let bodies: Vec<(i32, usize)> = results.iter().map(|r| (r.status, r.body.len())).collect();
+        assert_eq!(bodies[..4], [(200, 2), (200, 3), (401, 0), (200, 9)]);
+        assert_eq!(results[4].status, 404);
+        assert!(results.iter().all(|r| r.header("x-global") == Some("1")));
+    }
+}
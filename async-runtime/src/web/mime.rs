@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const DEFAULT_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("ico", "image/x-icon"),
+    ("pdf", "application/pdf"),
+    ("wasm", "application/wasm"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+];
+
+/// File extensions mapped to MIME types. Starts out with the common web
+/// types, more can be added per table.
+#[derive(Clone, Debug)]
+pub struct MimeTypes {
+    types: HashMap<String, String>,
+}
+
+impl Default for MimeTypes {
+    fn default() -> Self {
+        let types = DEFAULT_TYPES
+            .iter()
+            .map(|(ext, mime)| (String::from(*ext), String::from(*mime)))
+            .collect();
+        MimeTypes { types }
+    }
+}
+
+impl MimeTypes {
+    pub fn insert(&mut self, ext: &str, mime_type: &str) {
+        self.types
+            .insert(ext.to_ascii_lowercase(), String::from(mime_type));
+    }
+
+    /// The type for `path` by its extension, or `application/octet-stream`.
+    pub fn get(&self, path: &str) -> &str {
+        let ext = match path.rsplit_once('.') {
+            Some((_, ext)) if !ext.contains('/') => ext.to_ascii_lowercase(),
+            _ => return "application/octet-stream",
+        };
+        self.types
+            .get(&ext)
+            .map_or("application/octet-stream", |mime| mime.as_str())
+    }
+}
+
+/// Looks `path` up in the default table.
+pub fn from_path(path: &str) -> &'static str {
+    static DEFAULT: OnceLock<MimeTypes> = OnceLock::new();
+    DEFAULT.get_or_init(MimeTypes::default).get(path)
+}
+
+/// The `content-type` value for a MIME type, text is always sent as UTF-8.
+pub fn content_type(mime_type: &str) -> String {
+    if mime_type.starts_with("text/") {
+        format!("{}; charset=UTF-8", mime_type)
+    } else {
+        String::from(mime_type)
+    }
+}
@@ -1,4 +1,28 @@
+pub mod body;
+pub mod cache;
+pub mod compress;
+pub mod config;
+pub mod connection;
+pub mod date;
+pub mod extract;
+pub mod file;
+pub mod form;
+pub mod headers;
+pub mod into_response;
+pub mod json;
+pub mod middleware;
+pub mod mime;
 pub mod node;
+pub mod parser;
+pub mod query;
+pub mod range;
+pub mod request;
 pub mod response;
 pub mod router;
 pub mod routes;
+pub mod serve_dir;
+pub mod server;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod url;
+pub mod websocket;
@@ -1,12 +1,18 @@
-use super::router::HandlerFn;
+use std::collections::HashMap;
 
-pub struct Node {
-    pub nodes: Vec<Node>,
+/// Values captured from `{name}` and `*name` segments of a matched path.
+pub type Params = HashMap<String, String>;
+
+/// A segment of a route. Keys are literal segments, `{name}` matches any
+/// one segment and `*name` matches everything that's left of the path.
+#[derive(Debug)]
+pub struct Node<F> {
+    pub nodes: Vec<Node<F>>,
     pub key: String,
-    pub handler: Option<HandlerFn>,
+    pub handler: Option<F>,
 }
 
-impl Node {
+impl<F> Node<F> {
     pub fn new(key: &str) -> Self {
         Node {
             nodes: Vec::new(),
@@ -15,62 +21,84 @@ impl Node {
         }
     }
 
-    pub fn insert(&mut self, path: &str, f: HandlerFn) {
-        match path.split_once('/') {
-            Some((root, "")) => {
-                self.key = String::from(root);
-                self.handler = Some(f);
-            }
-            Some(("", path)) => self.insert(path, f),
-            Some((root, path)) => {
-                let node = self.nodes.iter_mut().find(|m| root == &m.key);
-                match node {
-                    Some(n) => n.insert(path, f),
-                    None => {
-                        let mut node = Node::new(root);
-                        node.insert(path, f);
-                        self.nodes.push(node);
-                    }
-                }
-            }
-            None => {
-                let mut node = Node::new(path);
-                node.handler = Some(f);
-                self.nodes.push(node);
-            }
+    pub fn is_param(&self) -> bool {
+        self.key.starts_with('{') && self.key.ends_with('}')
+    }
+
+    pub fn is_catch_all(&self) -> bool {
+        self.key.starts_with('*')
+    }
+
+    fn param_name(&self) -> &str {
+        if self.is_catch_all() {
+            &self.key[1..]
+        } else {
+            &self.key[1..self.key.len() - 1]
         }
     }
 
-    pub fn get(&self, path: &str) -> Option<&HandlerFn> {
-        match path.split_once('/') {
-            Some((root, "")) => {
-                if root == &self.key {
-                    self.handler.as_ref()
-                } else {
-                    None
-                }
+    pub fn insert(&mut self, path: &str, f: F) -> &mut F {
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            return self.handler.insert(f);
+        }
+
+        let (root, path) = path.split_once('/').unwrap_or((path, ""));
+        if root.starts_with('*') {
+            assert!(
+                path.is_empty(),
+                "catch-all segment {} must end the route",
+                root
+            );
+        }
+        let node = match self.nodes.iter().position(|m| root == m.key) {
+            Some(i) => &mut self.nodes[i],
+            None => {
+                self.nodes.push(Node::new(root));
+                self.nodes.last_mut().unwrap()
             }
-            Some(("", path)) => self.get(path),
-            Some((root, path)) => {
-                let node = self.nodes.iter().find(|m| root == &m.key);
-                if let Some(node) = node {
-                    node.get(path)
-                } else {
-                    None
-                }
+        };
+        node.insert(path, f)
+    }
+
+    /// Finds the handler for `path` along with the parameters it captured.
+    /// Literal segments win over `{name}`, which wins over `*name`.
+    pub fn get(&self, path: &str) -> Option<(&F, Params)> {
+        let mut params = Params::new();
+        let handler = self.find(path, &mut params)?;
+        Some((handler, params))
+    }
+
+    fn find(&self, path: &str, params: &mut Params) -> Option<&F> {
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            return self.handler.as_ref();
+        }
+
+        let (root, rest) = path.split_once('/').unwrap_or((path, ""));
+        let literal = self.nodes.iter().filter(|m| root == m.key);
+        for node in literal {
+            if let Some(handler) = node.find(rest, params) {
+                return Some(handler);
             }
-            None => {
-                let node = self.nodes.iter().find(|m| path == &m.key);
-                if let Some(node) = node {
-                    node.handler.as_ref()
-                } else {
-                    None
-                }
+        }
+
+        // try the next parameter if the rest of the path doesn't match below this one
+        for node in self.nodes.iter().filter(|m| m.is_param()) {
+            params.insert(String::from(node.param_name()), String::from(root));
+            if let Some(handler) = node.find(rest, params) {
+                return Some(handler);
             }
+            params.remove(node.param_name());
         }
+
+        let node = self.nodes.iter().find(|m| m.is_catch_all())?;
+        let handler = node.handler.as_ref()?;
+        params.insert(String::from(node.param_name()), String::from(path));
+        Some(handler)
     }
 }
-/*
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,23 +106,24 @@ mod tests {
     #[test]
     fn test_insert_routes() {
         let mut root = Node::new("");
-        root.insert("/", |_| Ok(()));
-        root.insert("/foo", |_| Ok(()));
-        root.insert("/foo/bar", |_| Ok(()));
+        root.insert("/", 0);
+        root.insert("/foo", 1);
+        root.insert("/foo/bar", 2);
 
-        println!("{:?}", root);
+        assert_eq!(root.handler, Some(0));
+        assert_eq!(root.nodes.len(), 1);
+        assert_eq!(root.nodes[0].handler, Some(1));
+        assert_eq!(root.nodes[0].nodes[0].key, "bar");
     }
 
     #[test]
     fn test_get_route() {
-        println!("{:?}", "foo".split_once('/'));
-        println!("{:?}", "".split_once('/'));
         let mut root = Node::new("");
-        root.insert("/", |_| Ok(()));
-        root.insert("/foo/bar", |_| Ok(()));
-        root.insert("/foo/foo", |_| Ok(()));
-        root.insert("/users/{id}/profile", |_| Ok(()));
-        root.insert("/companies/{id}/users/{userid}", |_| Ok(()));
+        root.insert("/", ());
+        root.insert("/foo/bar", ());
+        root.insert("/foo/foo", ());
+        root.insert("/users/{id}/profile", ());
+        root.insert("/companies/{id}/users/{userid}", ());
 
         assert!(root.get("/").is_some());
         assert!(root.get("/foo/bar").is_some());
@@ -107,9 +136,32 @@ mod tests {
         assert!(root.get("/users/bar/asdf").is_none());
         assert!(root.get("/companies/1234/asdf").is_none());
         assert!(root.get("/companies/1234/users").is_none());
-        assert!(root.get("/companies/1234/users/foo").is_some());
 
-        println!("{:?}", root);
+        let (_, params) = root.get("/companies/1234/users/foo").unwrap();
+        assert_eq!(params["id"], "1234");
+        assert_eq!(params["userid"], "foo");
+    }
+
+    #[test]
+    fn test_precedence_and_catch_all() {
+        let mut root = Node::new("");
+        root.insert("/users/{id}", "param");
+        root.insert("/users/me", "static");
+        root.insert("/users/{id}/posts", "posts");
+        root.insert("/files/*rest", "files");
+        root.insert("/files/readme", "readme");
+
+        assert_eq!(*root.get("/users/me").unwrap().0, "static");
+        assert_eq!(*root.get("/users/42").unwrap().0, "param");
+        // `me` only exists as a literal leaf, so this falls back to `{id}`
+        let (handler, params) = root.get("/users/me/posts").unwrap();
+        assert_eq!(*handler, "posts");
+        assert_eq!(params["id"], "me");
+
+        assert_eq!(*root.get("/files/readme").unwrap().0, "readme");
+        let (handler, params) = root.get("/files/css/site.css").unwrap();
+        assert_eq!(*handler, "files");
+        assert_eq!(params["rest"], "css/site.css");
+        assert!(root.get("/files").is_none());
     }
 }
-*/
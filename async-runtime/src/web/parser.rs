@@ -0,0 +1,332 @@
+use std::fmt;
+
+use super::body::BodyKind;
+use super::config::Limits;
+use super::headers::Headers;
+use super::request::{Method, Request, Version};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    BadRequest(&'static str),
+    UriTooLong,
+    HeaderFieldsTooLarge,
+    NotImplemented,
+    VersionNotSupported,
+}
+
+impl ParseError {
+    pub fn status(&self) -> i32 {
+        match self {
+            ParseError::BadRequest(_) => 400,
+            ParseError::UriTooLong => 414,
+            ParseError::HeaderFieldsTooLarge => 431,
+            ParseError::NotImplemented => 501,
+            ParseError::VersionNotSupported => 505,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            ParseError::UriTooLong => write!(f, "request target is too long"),
+            ParseError::HeaderFieldsTooLarge => write!(f, "request headers are too large"),
+            ParseError::NotImplemented => write!(f, "unsupported transfer encoding"),
+            ParseError::VersionNotSupported => write!(f, "unsupported http version"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Incremental parser for a request head. Feed it the connection's buffer
+/// each time more bytes arrive; it only rescans what it hasn't seen yet
+/// when looking for the blank line that ends the head.
+pub struct HeadParser<'limits> {
+    limits: &'limits Limits,
+    searched: usize,
+}
+
+impl<'limits> HeadParser<'limits> {
+    pub fn new(limits: &'limits Limits) -> Self {
+        HeadParser {
+            limits,
+            searched: 0,
+        }
+    }
+
+    /// Returns the request and how many bytes of `buf` it used, or `None`
+    /// if the head isn't complete yet.
+    pub fn parse(&mut self, buf: &[u8]) -> Result<Option<(Request, usize)>, ParseError> {
+        // a stray blank line ahead of the request line is allowed
+        let start = buf
+            .iter()
+            .position(|b| *b != b'\r' && *b != b'\n')
+            .unwrap_or(buf.len());
+        if start > self.limits.max_head_size {
+            return Err(ParseError::BadRequest("too many blank lines"));
+        }
+
+        let end = match find_head_end(buf, start.max(self.searched)) {
+            Some(end) => end,
+            None => {
+                self.searched = buf.len().saturating_sub(3).max(start);
+                self.check_partial(&buf[start..])?;
+                return Ok(None);
+            }
+        };
+
+        if end - start > self.limits.max_head_size {
+            self.check_partial(&buf[start..end])?;
+            return Err(ParseError::HeaderFieldsTooLarge);
+        }
+
+        let head = &buf[start..end];
+        let mut lines = head
+            .split(|b| *b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .filter(|line| !line.is_empty());
+
+        let request_line = lines
+            .next()
+            .ok_or(ParseError::BadRequest("empty request"))?;
+        let (method, target, version) = self.parse_request_line(request_line)?;
+
+        let mut headers = Headers::new();
+        for line in lines {
+            if headers.len() == self.limits.max_headers {
+                return Err(ParseError::HeaderFieldsTooLarge);
+            }
+            let (name, value) = parse_header_line(line)?;
+            headers.append(&name, &value);
+        }
+
+        if version == Version::Http11 && headers.get_all("host").count() != 1 {
+            return Err(ParseError::BadRequest("missing or repeated host header"));
+        }
+
+        let body_kind = parse_body_kind(&headers)?;
+        let request = Request::new(Method::parse(&method), target, version, headers, body_kind);
+        Ok(Some((request, end)))
+    }
+
+    // reject oversized input early rather than waiting for the rest of it
+    fn check_partial(&self, buf: &[u8]) -> Result<(), ParseError> {
+        let line_end = buf.iter().position(|b| *b == b'\n');
+        let request_line = &buf[..line_end.unwrap_or(buf.len())];
+        // method and version take up a handful of bytes around the target
+        if request_line.len() > self.limits.max_uri_length + 32 {
+            return Err(ParseError::UriTooLong);
+        }
+        if buf.len() > self.limits.max_head_size {
+            return Err(ParseError::HeaderFieldsTooLarge);
+        }
+        Ok(())
+    }
+
+    fn parse_request_line(&self, line: &[u8]) -> Result<(String, String, Version), ParseError> {
+        let line = std::str::from_utf8(line).map_err(|_| ParseError::BadRequest("request line"))?;
+        let mut parts = line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(m), Some(t), Some(v)) if parts.next().is_none() => (m, t, v),
+            _ => return Err(ParseError::BadRequest("malformed request line")),
+        };
+
+        if !is_token(method) {
+            return Err(ParseError::BadRequest("invalid method"));
+        }
+        if target.len() > self.limits.max_uri_length {
+            return Err(ParseError::UriTooLong);
+        }
+        if target.is_empty() || target.bytes().any(|b| b.is_ascii_control() || b == b' ') {
+            return Err(ParseError::BadRequest("invalid request target"));
+        }
+
+        let version = match version {
+            "HTTP/1.1" => Version::Http11,
+            "HTTP/1.0" => Version::Http10,
+            v if is_http_version(v) => return Err(ParseError::VersionNotSupported),
+            _ => return Err(ParseError::BadRequest("invalid http version")),
+        };
+
+        Ok((String::from(method), String::from(target), version))
+    }
+}
+
+// index just past the blank line that terminates the head
+fn find_head_end(buf: &[u8], from: usize) -> Option<usize> {
+    let mut i = from;
+    while i < buf.len() {
+        if buf[i] == b'\n' {
+            match buf.get(i + 1) {
+                Some(b'\n') => return Some(i + 2),
+                Some(b'\r') if buf.get(i + 2) == Some(&b'\n') => return Some(i + 3),
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+pub fn parse_header_line(line: &[u8]) -> Result<(String, String), ParseError> {
+    if line.starts_with(b" ") || line.starts_with(b"\t") {
+        return Err(ParseError::BadRequest("obsolete header line folding"));
+    }
+    let colon = line
+        .iter()
+        .position(|b| *b == b':')
+        .ok_or(ParseError::BadRequest("header without a colon"))?;
+
+    let name =
+        std::str::from_utf8(&line[..colon]).map_err(|_| ParseError::BadRequest("header name"))?;
+    if !is_token(name) {
+        return Err(ParseError::BadRequest("invalid header name"));
+    }
+
+    let value = &line[colon + 1..];
+    if value.iter().any(|b| b.is_ascii_control() && *b != b'\t') {
+        return Err(ParseError::BadRequest("invalid header value"));
+    }
+    let value = String::from_utf8_lossy(value);
+    Ok((
+        String::from(name),
+        String::from(value.trim_matches(|c| c == ' ' || c == '\t')),
+    ))
+}
+
+// how big a body may be depends on the route, which the router checks
+// once it's found one
+fn parse_body_kind(headers: &Headers) -> Result<BodyKind, ParseError> {
+    if headers.contains("transfer-encoding") {
+        // both framing headers at once is a request smuggling vector
+        if headers.contains("content-length") {
+            return Err(ParseError::BadRequest(
+                "both content-length and transfer-encoding",
+            ));
+        }
+        let codings: Vec<String> = headers
+            .get_all("transfer-encoding")
+            .flat_map(|v| v.split(','))
+            .map(|c| c.trim().to_ascii_lowercase())
+            .collect();
+        return match codings.as_slice() {
+            [coding] if coding == "chunked" => Ok(BodyKind::Chunked),
+            _ => Err(ParseError::NotImplemented),
+        };
+    }
+
+    let mut length = None;
+    for value in headers.get_all("content-length").flat_map(|v| v.split(',')) {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::BadRequest("invalid content-length"));
+        }
+        let n: u64 = value
+            .parse()
+            .map_err(|_| ParseError::BadRequest("invalid content-length"))?;
+        if length.is_some_and(|len| len != n) {
+            return Err(ParseError::BadRequest("conflicting content-length"));
+        }
+        length = Some(n);
+    }
+
+    match length {
+        Some(0) | None => Ok(BodyKind::Empty),
+        Some(n) => Ok(BodyKind::Length(n)),
+    }
+}
+
+/// Parses the size line that starts every chunk, ignoring chunk extensions.
+pub fn parse_chunk_size(line: &[u8]) -> Result<u64, ParseError> {
+    let size = line.split(|b| *b == b';').next().unwrap_or_default();
+    let size = std::str::from_utf8(size)
+        .map_err(|_| ParseError::BadRequest("chunk size"))?
+        .trim_matches(|c| c == ' ' || c == '\t');
+    if size.is_empty() || size.len() > 16 {
+        return Err(ParseError::BadRequest("chunk size"));
+    }
+    u64::from_str_radix(size, 16).map_err(|_| ParseError::BadRequest("chunk size"))
+}
+
+fn is_http_version(v: &str) -> bool {
+    match v.strip_prefix("HTTP/").map(|v| v.as_bytes()) {
+        Some([major, b'.', minor]) => major.is_ascii_digit() && minor.is_ascii_digit(),
+        Some([major]) => major.is_ascii_digit(),
+        _ => false,
+    }
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(buf: &[u8]) -> Result<Option<(Request, usize)>, ParseError> {
+        let limits = Limits::default();
+        HeadParser::new(&limits).parse(buf)
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let buf = b"GET /todo?filter=done HTTP/1.1\r\nHost: localhost\r\nAccept:  text/html \r\n\r\nleftover";
+        let (req, len) = parse(buf).unwrap().unwrap();
+        assert_eq!(req.method, Method::GET);
+        assert_eq!(req.target, "/todo?filter=done");
+        assert_eq!(req.version, Version::Http11);
+        assert_eq!(req.header("accept"), Some("text/html"));
+        assert_eq!(req.body_kind(), BodyKind::Empty);
+        assert_eq!(&buf[len..], b"leftover");
+    }
+
+    #[test]
+    fn test_parse_incremental() {
+        let buf = b"POST /todo HTTP/1.0\nContent-Length: 5\n\nhello";
+        let limits = Limits::default();
+        let mut parser = HeadParser::new(&limits);
+        for end in 0..38 {
+            assert!(parser.parse(&buf[..end]).unwrap().is_none());
+        }
+        let (req, len) = parser.parse(buf).unwrap().unwrap();
+        assert_eq!(req.version, Version::Http10);
+        assert_eq!(req.body_kind(), BodyKind::Length(5));
+        assert_eq!(&buf[len..], b"hello");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let status = |buf: &[u8]| parse(buf).unwrap_err().status();
+        assert_eq!(status(b"GET /\r\n\r\n"), 400);
+        assert_eq!(status(b"GET / HTTP/1.1\r\n\r\n"), 400);
+        assert_eq!(status(b"GET / HTTP/2.0\r\nHost: a\r\n\r\n"), 505);
+        assert_eq!(status(b"GET / HTTP/1.1\r\nHost : a\r\n\r\n"), 400);
+        assert_eq!(status(b"GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n"), 400);
+        assert_eq!(
+            status(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n"),
+            400
+        );
+        assert_eq!(
+            status(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            501
+        );
+
+        let long_target = format!("GET /{} HTTP/1.1\r\n", "a".repeat(4096));
+        assert_eq!(status(long_target.as_bytes()), 414);
+        let long_header = format!("GET / HTTP/1.1\r\nX-Big: {}\r\n", "a".repeat(9000));
+        assert_eq!(status(long_header.as_bytes()), 431);
+    }
+
+    #[test]
+    fn test_parse_chunk_size() {
+        assert_eq!(parse_chunk_size(b"1a"), Ok(26));
+        assert_eq!(parse_chunk_size(b"0;name=value"), Ok(0));
+        assert!(parse_chunk_size(b"xyz").is_err());
+        assert!(parse_chunk_size(b"").is_err());
+    }
+}
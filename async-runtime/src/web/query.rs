@@ -0,0 +1,167 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::extract::FromRequestParts;
+use super::into_response::IntoResponse;
+use super::json::error_response;
+use super::request::Request;
+use super::response::Response;
+use super::url::percent_decode;
+
+/// The decoded `name=value` pairs of a query string, in the order they
+/// were sent. A name can show up more than once, as in `?tag=a&tag=b`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryMap {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryMap {
+    /// Splits and decodes a query string, `+` standing in for a space.
+    /// Fails on an escape that doesn't decode to UTF-8.
+    pub fn parse(query: &str) -> Result<Self, QueryRejection> {
+        let decode = |s: &str| {
+            percent_decode(&s.replace('+', " "))
+                .ok_or_else(|| QueryRejection::Malformed(String::from(s)))
+        };
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Ok((decode(name)?, decode(value)?))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(QueryMap { pairs })
+    }
+
+    /// The first value given for `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// A value deserialized from the query string of a request.
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> Query<T> {
+    pub fn from_request(req: &Request) -> Result<Self, QueryRejection> {
+        let query = req.query_string().unwrap_or_default();
+        // turn away bad escapes, which the deserializer lets through as is
+        QueryMap::parse(query)?;
+        match serde_urlencoded::from_str(query) {
+            Ok(value) => Ok(Query(value)),
+            Err(e) => Err(QueryRejection::Invalid(e.to_string())),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequestParts for Query<T> {
+    type Rejection = QueryRejection;
+
+    fn from_request_parts(req: &Request) -> LocalBoxedFuture<'_, Result<Self, QueryRejection>> {
+        let query = Self::from_request(req);
+        Box::pin(async move { query })
+    }
+}
+
+/// Why a query string couldn't be read. Either way it's a 400, with a
+/// JSON body describing the error.
+#[derive(Debug)]
+pub enum QueryRejection {
+    // the part of the query with a bad escape
+    Malformed(String),
+    // parameters that don't fit what the handler asked for
+    Invalid(String),
+}
+
+impl QueryRejection {
+    pub fn status(&self) -> i32 {
+        400
+    }
+}
+
+impl fmt::Display for QueryRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryRejection::Malformed(part) => write!(f, "invalid escape in query: {}", part),
+            QueryRejection::Invalid(e) => write!(f, "invalid query parameters: {}", e),
+        }
+    }
+}
+
+impl IntoResponse for QueryRejection {
+    fn into_response(self) -> Response {
+        error_response(self.status(), self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::web::request::Method;
+    use crate::web::test_util::request;
+
+    #[derive(Debug, Deserialize)]
+    struct Filter {
+        done: bool,
+        page: Option<u32>,
+    }
+
+    #[test]
+    fn test_query() {
+        let get = |target| request(Method::GET, target, &[], b"");
+        let req = get("/todo?tag=a%20b&tag=c+d&&flag&done=true");
+        assert_eq!(req.path(), "/todo");
+        let query = req.query().unwrap();
+        assert_eq!(query.get_all("tag").collect::<Vec<_>>(), ["a b", "c d"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.len(), 4);
+        assert!(get("/todo").query().unwrap().is_empty());
+        assert!(get("/todo?bad=%zz").query().is_err());
+
+        let Query(filter) = Query::<Filter>::from_request(&get("/?done=true&page=2")).unwrap();
+        assert!(filter.done);
+        assert_eq!(filter.page, Some(2));
+        let invalid = |target| Query::<Filter>::from_request(&get(target)).err().unwrap();
+        assert!(matches!(
+            invalid("/?done=maybe"),
+            QueryRejection::Invalid(_)
+        ));
+        assert!(matches!(invalid("/?page=1"), QueryRejection::Invalid(_)));
+        assert!(matches!(
+            invalid("/?done=true&x=%ff"),
+            QueryRejection::Malformed(_)
+        ));
+    }
+}
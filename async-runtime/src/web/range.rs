@@ -0,0 +1,299 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+
+use super::date::parse_http_date;
+use super::file::Segment;
+use super::middleware::{Middleware, Next};
+use super::request::{Method, Request};
+use super::response::{Response, ResponseBody};
+
+// past this many ranges a request is more likely abuse than a real client
+const MAX_RANGES: usize = 32;
+
+/// Parses a `Range` header against a body of `len` bytes into inclusive
+/// `(first, last)` byte positions, in order and with overlapping or
+/// adjacent ranges merged. `None` means the header should be ignored and
+/// the whole body sent, an empty list that nothing in it can be satisfied.
+pub fn parse_range(header: &str, len: u64) -> Option<Vec<(u64, u64)>> {
+    let (unit, specs) = header.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (first, last) = spec.split_once('-')?;
+        let range = match (first.trim(), last.trim()) {
+            ("", suffix) => {
+                let suffix: u64 = parse_pos(suffix)?;
+                (suffix > 0 && len > 0).then(|| (len.saturating_sub(suffix), len - 1))
+            }
+            (first, "") => {
+                let first = parse_pos(first)?;
+                (first < len).then(|| (first, len - 1))
+            }
+            (first, last) => {
+                let (first, last) = (parse_pos(first)?, parse_pos(last)?);
+                if first > last {
+                    return None;
+                }
+                (first < len).then(|| (first, last.min(len - 1)))
+            }
+        };
+        ranges.extend(range);
+    }
+
+    // so asking for the same bytes over and over gets them once
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some((_, end)) if first <= end.saturating_add(1) => *end = (*end).max(last),
+            _ => merged.push((first, last)),
+        }
+    }
+    if merged.len() > MAX_RANGES {
+        return None;
+    }
+    Some(merged)
+}
+
+fn parse_pos(pos: &str) -> Option<u64> {
+    if pos.is_empty() || !pos.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    pos.parse().ok()
+}
+
+// an If-Range only lets the range through if the representation is unchanged
+fn if_range_matches(res: &Response, if_range: &str) -> bool {
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') {
+        // strong comparison, a weak tag never matches
+        return res.header("etag") == Some(if_range);
+    }
+    let last_modified = res.header("last-modified").and_then(parse_http_date);
+    last_modified.is_some() && last_modified == parse_http_date(if_range)
+}
+
+/// Answers `Range` requests for responses that advertise
+/// `accept-ranges: bytes`, with a `206 Partial Content` holding either the
+/// one range or a `multipart/byteranges` body, or a 416 if none of the
+/// ranges fall within the body.
+pub struct Ranges;
+
+impl Middleware for Ranges {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> LocalBoxedFuture<'a, Response> {
+        let range = match req.method {
+            Method::GET => req.header("range").map(String::from),
+            _ => None,
+        };
+        let if_range = req.header("if-range").map(String::from);
+        Box::pin(async move {
+            let res = next.run(req).await;
+            let range = match range {
+                Some(range) if res.status == 200 => range,
+                _ => return res,
+            };
+            if res.header("accept-ranges") != Some("bytes") {
+                return res;
+            }
+            if if_range.is_some_and(|if_range| !if_range_matches(&res, &if_range)) {
+                return res;
+            }
+            // a stream's length isn't known up front, so it's sent whole
+            let len = match &res.body {
+                ResponseBody::Bytes(bytes) => bytes.len() as u64,
+                ResponseBody::File(file) => file.len(),
+                _ => return res,
+            };
+            match parse_range(&range, len) {
+                None => res,
+                Some(ranges) if ranges.is_empty() => not_satisfiable(res, len),
+                Some(ranges) if ranges.len() == 1 => single(res, ranges[0]),
+                Some(ranges) => multipart(res, &ranges),
+            }
+        })
+    }
+}
+
+fn not_satisfiable(mut res: Response, len: u64) -> Response {
+    res.status = 416;
+    res.body = ResponseBody::Empty;
+    res.headers.remove("content-type");
+    res.set_header("content-range", &format!("bytes */{}", len));
+    res
+}
+
+fn single(mut res: Response, (first, last): (u64, u64)) -> Response {
+    let len = res.body.len();
+    res.body = match std::mem::take(&mut res.body) {
+        ResponseBody::Bytes(mut bytes) => {
+            bytes.truncate(last as usize + 1);
+            bytes.drain(..first as usize);
+            ResponseBody::Bytes(bytes)
+        }
+        ResponseBody::File(file) => {
+            ResponseBody::File(file.with_segments(vec![Segment::Range(first, last)]))
+        }
+        body => body,
+    };
+    res.status = 206;
+    let content_range = format!("bytes {}-{}/{}", first, last, len);
+    res.set_header("content-range", &content_range);
+    res
+}
+
+fn multipart(mut res: Response, ranges: &[(u64, u64)]) -> Response {
+    let len = res.body.len();
+    let boundary = boundary();
+    let content_type = res.header("content-type").map(String::from);
+
+    let mut segments = Vec::new();
+    for &(first, last) in ranges {
+        let mut head = format!("--{}\r\n", boundary);
+        if let Some(content_type) = &content_type {
+            head.push_str(&format!("content-type: {}\r\n", content_type));
+        }
+        head.push_str(&format!(
+            "content-range: bytes {}-{}/{}\r\n\r\n",
+            first, last, len
+        ));
+        segments.push(Segment::Bytes(head.into_bytes()));
+        segments.push(Segment::Range(first, last));
+        segments.push(Segment::Bytes(b"\r\n".to_vec()));
+    }
+    segments.push(Segment::Bytes(format!("--{}--\r\n", boundary).into_bytes()));
+
+    res.body = match std::mem::take(&mut res.body) {
+        ResponseBody::Bytes(bytes) => {
+            let mut body = Vec::new();
+            for segment in segments {
+                match segment {
+                    Segment::Bytes(part) => body.extend_from_slice(&part),
+                    Segment::Range(first, last) => {
+                        body.extend_from_slice(&bytes[first as usize..=last as usize])
+                    }
+                }
+            }
+            ResponseBody::Bytes(body)
+        }
+        ResponseBody::File(file) => ResponseBody::File(file.with_segments(segments)),
+        body => return Response { body, ..res },
+    };
+    res.status = 206;
+    let content_type = format!("multipart/byteranges; boundary={}", boundary);
+    res.set_header("content-type", &content_type);
+    res
+}
+
+// doesn't need to be unpredictable, only unlikely to show up in the file
+fn boundary() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:016x}{:08x}", nanos, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::async_io::stream;
+    use crate::web::file::FileBody;
+    use crate::web::router::Router;
+    use crate::web::test_util::{request, run};
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some(vec![(0, 99)]));
+        assert_eq!(parse_range("bytes=900-", 1000), Some(vec![(900, 999)]));
+        assert_eq!(parse_range("bytes=-100", 1000), Some(vec![(900, 999)]));
+        assert_eq!(parse_range("bytes=-5000", 1000), Some(vec![(0, 999)]));
+        assert_eq!(parse_range("bytes=990-2000", 1000), Some(vec![(990, 999)]));
+        assert_eq!(
+            parse_range("bytes=0-0, ,-1", 1000),
+            Some(vec![(0, 0), (999, 999)])
+        );
+        assert_eq!(
+            parse_range(&format!("bytes={}", "0-,".repeat(40)), 1000),
+            Some(vec![(0, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=20-29,0-4,25-,5-9", 1000),
+            Some(vec![(0, 9), (20, 999)])
+        );
+        // nothing satisfiable
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(parse_range("bytes=-0", 1000), Some(vec![]));
+        // ignored altogether
+        assert_eq!(parse_range("bytes=5-1", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn test_if_range() {
+        let mut res = Response::new(200, "0123456789");
+        res.set_header("etag", "\"v1\"");
+        res.set_header("last-modified", "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert!(if_range_matches(&res, "\"v1\""));
+        assert!(!if_range_matches(&res, "W/\"v1\""));
+        assert!(!if_range_matches(&res, "\"v2\""));
+        assert!(if_range_matches(&res, "Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert!(!if_range_matches(&res, "Sun, 06 Nov 1994 08:49:38 GMT"));
+
+        let res = single(res, (2, 4));
+        assert_eq!(res.status, 206);
+        assert_eq!(res.header("content-range"), Some("bytes 2-4/10"));
+        assert_eq!(res.body.len(), 3);
+    }
+
+    #[test]
+    fn test_file_ranges() {
+        let path = std::env::temp_dir().join(format!("ranges-{}", std::process::id()));
+        fs::write(&path, "0123456789").unwrap();
+        let file = || {
+            let body = FileBody::new(fs::File::open(&path).unwrap(), 10);
+            Response::new(200, ResponseBody::File(body))
+        };
+        let contents = |res: Response| match res.body {
+            ResponseBody::File(mut file) => file.read_all().unwrap(),
+            body => panic!("expected a file body, got {:?}", body),
+        };
+
+        let res = single(file(), (2, 4));
+        assert_eq!(res.header("content-range"), Some("bytes 2-4/10"));
+        assert_eq!(contents(res), b"234");
+
+        let res = multipart(file(), &[(0, 1), (8, 9)]);
+        assert_eq!(res.status, 206);
+        let body = String::from_utf8(contents(res)).unwrap();
+        assert!(body.contains("content-range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("content-range: bytes 8-9/10\r\n\r\n89\r\n"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stream_passes_through() {
+        let mut router = Router::new();
+        router.wrap(Ranges);
+        router.insert(Method::GET, "/", || async {
+            let chunks = stream::iter([Ok::<_, std::io::Error>("0123456789")]);
+            Response::builder()
+                .header("accept-ranges", "bytes")
+                .body(ResponseBody::stream(chunks))
+        });
+
+        let req = request(Method::GET, "/", &[("range", "bytes=2-4")], b"");
+        let res = run(&Rc::new(router), req);
+        assert_eq!(res.status, 200);
+        assert!(res.body.is_stream());
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::rc::Rc;
+
+use super::body::{Body, BodyKind};
+use super::extract::StateMap;
+use super::headers::Headers;
+use super::node::Params;
+use super::query::{QueryMap, QueryRejection};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Method {
+    GET,
+    HEAD,
+    POST,
+    PUT,
+    PATCH,
+    DELETE,
+    OPTIONS,
+    // any other method token, matched case-sensitively
+    Custom(String),
+}
+
+impl Method {
+    pub fn parse(method: &str) -> Method {
+        match method {
+            "GET" => Method::GET,
+            "HEAD" => Method::HEAD,
+            "POST" => Method::POST,
+            "PUT" => Method::PUT,
+            "PATCH" => Method::PATCH,
+            "DELETE" => Method::DELETE,
+            "OPTIONS" => Method::OPTIONS,
+            _ => Method::Custom(String::from(method)),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::GET => "GET",
+            Method::HEAD => "HEAD",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::PATCH => "PATCH",
+            Method::DELETE => "DELETE",
+            Method::OPTIONS => "OPTIONS",
+            Method::Custom(method) => method,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Http10 => write!(f, "HTTP/1.0"),
+            Version::Http11 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+/// A parsed request along with the parameters captured by its route. The
+/// body is still on the connection and is read through `body()`.
+#[derive(Debug)]
+pub struct Request {
+    pub method: Method,
+    pub target: String,
+    pub version: Version,
+    pub headers: Headers,
+    pub params: Params,
+    body_kind: BodyKind,
+    body: Body,
+    // the router's shared state, handed out through `State`
+    state: Rc<StateMap>,
+}
+
+impl Request {
+    pub fn new(
+        method: Method,
+        target: String,
+        version: Version,
+        headers: Headers,
+        body_kind: BodyKind,
+    ) -> Self {
+        Request {
+            method,
+            target,
+            version,
+            headers,
+            params: Params::new(),
+            body_kind,
+            body: Body::empty(),
+            state: Rc::default(),
+        }
+    }
+
+    /// The target without its query string, which is what gets routed.
+    pub fn path(&self) -> &str {
+        self.target
+            .split_once('?')
+            .map_or(self.target.as_str(), |(path, _)| path)
+    }
+
+    /// The raw query string, without the `?`.
+    pub fn query_string(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, query)| query)
+    }
+
+    /// The decoded parameters of the query string.
+    pub fn query(&self) -> Result<QueryMap, QueryRejection> {
+        QueryMap::parse(self.query_string().unwrap_or_default())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|value| value.as_str())
+    }
+
+    pub fn body_kind(&self) -> BodyKind {
+        self.body_kind
+    }
+
+    pub fn body(&mut self) -> &mut Body {
+        &mut self.body
+    }
+
+    pub fn set_body(&mut self, body: Body) {
+        self.body = body;
+    }
+
+    /// The state of type `T` given to the router, if there is any.
+    pub fn state<T: Clone + 'static>(&self) -> Option<T> {
+        self.state.get()
+    }
+
+    pub(crate) fn set_state(&mut self, state: Rc<StateMap>) {
+        self.state = state;
+    }
+}
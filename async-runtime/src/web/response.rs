@@ -1,82 +1,280 @@
-use std::io::{Read, Result};
+use std::fmt;
+use std::future::Future;
+use std::io::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use colored::Colorize;
 
+use crate::async_io::stream::{Stream, StreamExt};
+use crate::async_io::task_queue::LocalBoxedFuture;
+use crate::async_io::timer::{sleep, Sleep};
 use crate::async_net::client::TcpClient;
 
-pub struct Response {
-    client: TcpClient,
-}
+use super::connection::Connection;
+use super::file::FileBody;
+use super::headers::Headers;
+use super::into_response::IntoResponse;
+use super::request::{Request, Version};
 
+/// Maps codes outside of the valid range to a 500, anything else is sent
+/// as is even if it has no registered reason phrase.
 pub fn status_code(code: i32) -> i32 {
     match code {
-        200 | 400 | 404 => code,
-        _ => 501,
+        100..=599 => code,
+        _ => 500,
     }
 }
 
 pub fn status(code: i32) -> &'static str {
     match code {
+        100 => "CONTINUE",
+        101 => "SWITCHING PROTOCOLS",
+        102 => "PROCESSING",
+        103 => "EARLY HINTS",
         200 => "OK",
+        201 => "CREATED",
+        202 => "ACCEPTED",
+        203 => "NON-AUTHORITATIVE INFORMATION",
+        204 => "NO CONTENT",
+        205 => "RESET CONTENT",
+        206 => "PARTIAL CONTENT",
+        207 => "MULTI-STATUS",
+        208 => "ALREADY REPORTED",
+        226 => "IM USED",
+        300 => "MULTIPLE CHOICES",
+        301 => "MOVED PERMANENTLY",
+        302 => "FOUND",
+        303 => "SEE OTHER",
+        304 => "NOT MODIFIED",
+        305 => "USE PROXY",
+        307 => "TEMPORARY REDIRECT",
+        308 => "PERMANENT REDIRECT",
         400 => "BAD REQUEST",
+        401 => "UNAUTHORIZED",
+        402 => "PAYMENT REQUIRED",
+        403 => "FORBIDDEN",
         404 => "NOT FOUND",
-        _ => "NOT IMPLEMENTED",
+        405 => "METHOD NOT ALLOWED",
+        406 => "NOT ACCEPTABLE",
+        407 => "PROXY AUTHENTICATION REQUIRED",
+        408 => "REQUEST TIMEOUT",
+        409 => "CONFLICT",
+        410 => "GONE",
+        411 => "LENGTH REQUIRED",
+        412 => "PRECONDITION FAILED",
+        413 => "PAYLOAD TOO LARGE",
+        414 => "URI TOO LONG",
+        415 => "UNSUPPORTED MEDIA TYPE",
+        416 => "RANGE NOT SATISFIABLE",
+        417 => "EXPECTATION FAILED",
+        418 => "I'M A TEAPOT",
+        421 => "MISDIRECTED REQUEST",
+        422 => "UNPROCESSABLE CONTENT",
+        423 => "LOCKED",
+        424 => "FAILED DEPENDENCY",
+        425 => "TOO EARLY",
+        426 => "UPGRADE REQUIRED",
+        428 => "PRECONDITION REQUIRED",
+        429 => "TOO MANY REQUESTS",
+        431 => "REQUEST HEADER FIELDS TOO LARGE",
+        451 => "UNAVAILABLE FOR LEGAL REASONS",
+        500 => "INTERNAL SERVER ERROR",
+        501 => "NOT IMPLEMENTED",
+        502 => "BAD GATEWAY",
+        503 => "SERVICE UNAVAILABLE",
+        504 => "GATEWAY TIMEOUT",
+        505 => "HTTP VERSION NOT SUPPORTED",
+        506 => "VARIANT ALSO NEGOTIATES",
+        507 => "INSUFFICIENT STORAGE",
+        508 => "LOOP DETECTED",
+        510 => "NOT EXTENDED",
+        511 => "NETWORK AUTHENTICATION REQUIRED",
+        _ => "",
     }
 }
 
-impl Response {
-    pub fn new(client: TcpClient) -> Self {
-        Response { client }
-    }
-
-    pub fn parse_mime_type(&self, key: &str) -> &str {
-        if let Some((_, ext)) = key.rsplit_once(".") {
-            match ext {
-                "html" => "text/html",
-                "css" => "text/css",
-                "js" => "text/javascript",
-                "jpg" => "image/jpeg",
-                "jpeg" => "image/jpeg",
-                "png" => "image/png",
-                "ico" => "image/x-icon",
-                "pdf" => "application/pdf",
-                _ => "text/plain",
-            }
-        } else {
-            "text/plain"
+// informational, 204 and 304 responses never carry a body
+fn allows_body(code: i32) -> bool {
+    !matches!(code, 100..=199 | 204 | 304)
+}
+
+/// A piece of a streamed body. `Trailers` ends the body, its fields are
+/// sent after the last chunk when the body is chunked and dropped otherwise.
+#[derive(Debug)]
+pub enum Chunk {
+    Data(Vec<u8>),
+    Trailers(Headers),
+}
+
+impl From<Vec<u8>> for Chunk {
+    fn from(bytes: Vec<u8>) -> Self {
+        Chunk::Data(bytes)
+    }
+}
+
+impl From<String> for Chunk {
+    fn from(text: String) -> Self {
+        Chunk::Data(text.into_bytes())
+    }
+}
+
+impl From<&'static str> for Chunk {
+    fn from(text: &'static str) -> Self {
+        Chunk::Data(text.as_bytes().to_vec())
+    }
+}
+
+impl From<Headers> for Chunk {
+    fn from(trailers: Headers) -> Self {
+        Chunk::Trailers(trailers)
+    }
+}
+
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Chunk>>>>;
+
+/// Takes over a connection once a `101` response to an upgrade is sent.
+pub type OnUpgrade = Box<dyn FnOnce(Connection) -> LocalBoxedFuture<'static, ()>>;
+
+/// What gets written after the head of a response.
+#[derive(Default)]
+pub enum ResponseBody {
+    #[default]
+    Empty,
+    Bytes(Vec<u8>),
+    // written as it's produced, with chunked framing on HTTP/1.1 and
+    // until the connection closes on HTTP/1.0
+    Stream(BodyStream),
+    // read off a file a piece at a time as it's written
+    File(FileBody),
+    // whatever protocol the connection switches to, run by the router
+    // after a `101` response
+    Upgrade(OnUpgrade),
+}
+
+impl ResponseBody {
+    /// A body sent chunk by chunk as `stream` yields them. An error ends
+    /// the response early by closing the connection.
+    pub fn stream<S, T>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<T>> + 'static,
+        T: Into<Chunk>,
+    {
+        let chunks = Box::pin(stream).map(|item: Result<T>| item.map(Into::into));
+        ResponseBody::Stream(Box::pin(chunks))
+    }
+
+    /// The length of a body held in memory or read off a file. A stream
+    /// counts as empty since its length isn't known until it's been sent.
+    pub fn len(&self) -> usize {
+        match self {
+            ResponseBody::Empty | ResponseBody::Stream(_) | ResponseBody::Upgrade(_) => 0,
+            ResponseBody::Bytes(bytes) => bytes.len(),
+            ResponseBody::File(file) => file.len() as usize,
         }
     }
 
-    pub fn read_response_file(&self, path: &str) -> Vec<u8> {
-        let file = std::fs::File::open(String::from(path)).unwrap();
-        let mut buf = Vec::new();
-        let mut reader = std::io::BufReader::new(file);
-        reader.read_to_end(&mut buf).unwrap();
-        buf
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    pub async fn send_file(&mut self, code: i32, path: &str) -> Result<()> {
-        let contents = self.read_response_file(path.clone());
-        let len = contents.len();
+    pub fn is_stream(&self) -> bool {
+        matches!(self, ResponseBody::Stream(_))
+    }
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseBody::Empty => f.write_str("Empty"),
+            ResponseBody::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            ResponseBody::Stream(_) => f.write_str("Stream"),
+            ResponseBody::File(file) => f.debug_tuple("File").field(file).finish(),
+            ResponseBody::Upgrade(_) => f.write_str("Upgrade"),
+        }
+    }
+}
 
-        let mime_type = self.parse_mime_type(path);
-        let content = format!(
-            "HTTP/1.0 {} {}
-content-type: {}; charset=UTF-8
-content-length: {}
+impl From<Vec<u8>> for ResponseBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        ResponseBody::Bytes(bytes)
+    }
+}
 
-",
-            status_code(code),
-            status(code),
-            mime_type,
-            len
-        );
+impl From<&[u8]> for ResponseBody {
+    fn from(bytes: &[u8]) -> Self {
+        ResponseBody::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(text: String) -> Self {
+        ResponseBody::Bytes(text.into_bytes())
+    }
+}
+
+impl From<&str> for ResponseBody {
+    fn from(text: &str) -> Self {
+        ResponseBody::Bytes(text.as_bytes().to_vec())
+    }
+}
+
+impl From<()> for ResponseBody {
+    fn from(_: ()) -> Self {
+        ResponseBody::Empty
+    }
+}
 
-        let bytes = content.as_bytes();
-        self.client.write(&bytes).await?;
-        self.client.write(&contents).await?;
-        self.client.flush();
+/// A response returned by a handler. The router fills in the framing
+/// headers (`content-length`, `connection`) when it writes it out.
+#[derive(Debug, Default)]
+pub struct Response {
+    pub status: i32,
+    pub headers: Headers,
+    pub body: ResponseBody,
+}
 
+impl Response {
+    pub fn new(status: i32, body: impl Into<ResponseBody>) -> Self {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder {
+            response: Response::new(200, ()),
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Adds a header to the response, replacing any with the same name.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name, value);
+    }
+
+    /// Writes the response to a client that sent an HTTP `version`
+    /// request, leaving out the body when answering a `HEAD` request.
+    /// Streamed bodies are chunked for HTTP/1.1 clients, an HTTP/1.0 one
+    /// has to be sent `connection: close` since only the end of the
+    /// connection marks the end of the body.
+    pub async fn write(
+        &mut self,
+        client: &mut TcpClient,
+        head_only: bool,
+        version: Version,
+    ) -> Result<()> {
+        let code = status_code(self.status);
+        let chunked = version == Version::Http11;
+        let content = self.head(chunked);
+
+        client.write_all(content.as_bytes()).await?;
         println!(
             "{} {:?} writing response \n{}",
             format!("[{}]", std::process::id()).truecolor(0, 255, 136),
@@ -84,6 +282,265 @@ content-length: {}
             content
         );
 
+        if !head_only && allows_body(code) {
+            match &mut self.body {
+                ResponseBody::Empty | ResponseBody::Upgrade(_) => {}
+                ResponseBody::Bytes(bytes) => client.write_all(bytes).await?,
+                ResponseBody::File(file) => file.write(client).await?,
+                ResponseBody::Stream(stream) => write_stream(client, stream, chunked).await?,
+            }
+        }
+        client.flush();
+
         Ok(())
     }
+
+    // the status line and headers, ending with the blank line
+    fn head(&self, chunked: bool) -> String {
+        let code = status_code(self.status);
+        let mut content = format!("HTTP/1.1 {} {}\r\n", code, status(code));
+        for (name, value) in self.headers.iter() {
+            content.push_str(&field(name, value));
+        }
+        if allows_body(code) {
+            if self.body.is_stream() {
+                if chunked {
+                    content.push_str("transfer-encoding: chunked\r\n");
+                }
+            } else if !self.headers.contains("content-length") {
+                content.push_str(&format!("content-length: {}\r\n", self.body.len()));
+            }
+        }
+        content.push_str("\r\n");
+        content
+    }
+}
+
+// a header or trailer line. Values often come from the request, as in a
+// redirect's `location`, so line breaks are stripped rather than letting
+// them end the line early and start headers of their own
+fn field(name: &str, value: &str) -> String {
+    let single_line = |value: &str| value.replace(['\r', '\n'], "");
+    format!("{}: {}\r\n", single_line(name), single_line(value))
+}
+
+async fn write_stream(
+    client: &mut TcpClient,
+    stream: &mut BodyStream,
+    chunked: bool,
+) -> Result<()> {
+    let mut trailers = None;
+    while let Some(chunk) = stream.next().await {
+        match chunk? {
+            // an empty chunk would mark the end of the body
+            Chunk::Data(data) if data.is_empty() => {}
+            Chunk::Data(data) if chunked => {
+                let mut frame = format!("{:x}\r\n", data.len()).into_bytes();
+                frame.extend_from_slice(&data);
+                frame.extend_from_slice(b"\r\n");
+                client.write_all(&frame).await?;
+            }
+            Chunk::Data(data) => client.write_all(&data).await?,
+            Chunk::Trailers(fields) => {
+                trailers = Some(fields);
+                break;
+            }
+        }
+    }
+
+    if chunked {
+        let mut end = String::from("0\r\n");
+        for (name, value) in trailers.iter().flat_map(|fields| fields.iter()) {
+            end.push_str(&field(name, value));
+        }
+        end.push_str("\r\n");
+        client.write_all(end.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+pub struct ResponseBuilder {
+    response: Response,
+}
+
+impl ResponseBuilder {
+    pub fn status(mut self, status: i32) -> Self {
+        self.response.status = status;
+        self
+    }
+
+    /// Appends a header, so repeated names like `set-cookie` are all sent.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.response.headers.append(name, value);
+        self
+    }
+
+    pub fn content_type(self, content_type: &str) -> Self {
+        self.header("content-type", content_type)
+    }
+
+    pub fn body(mut self, body: impl Into<ResponseBody>) -> Response {
+        self.response.body = body.into();
+        self.response
+    }
+}
+
+/// A server-sent event, written as the `id:`, `event:`, `retry:` and
+/// `data:` fields of one `text/event-stream` message.
+#[derive(Clone, Debug, Default)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    retry: Option<Duration>,
+    data: String,
+}
+
+impl Event {
+    pub fn new(data: impl Into<String>) -> Self {
+        Event {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Sent back by the browser as `Last-Event-ID` when it reconnects.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// The type the browser dispatches the event as, `message` if unset.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// How long the browser waits before reconnecting after a drop.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    fn encode(&self) -> String {
+        // a line break would end the field early, or the whole event
+        let single_line = |value: &str| value.replace(['\r', '\n'], "");
+        let mut message = String::new();
+        if let Some(id) = &self.id {
+            message.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        if let Some(event) = &self.event {
+            message.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(retry) = self.retry {
+            message.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        for line in self.data.split('\n') {
+            message.push_str(&format!("data: {}\n", line.trim_end_matches('\r')));
+        }
+        message.push('\n');
+        message
+    }
+}
+
+/// A `text/event-stream` response that keeps the connection open and sends
+/// each event of `events` as it comes, with a comment as a heartbeat when
+/// it's been quiet for a while so proxies don't time the connection out.
+pub struct Sse {
+    events: Pin<Box<dyn Stream<Item = Event>>>,
+    heartbeat: Option<Duration>,
+}
+
+impl Sse {
+    pub fn new(events: impl Stream<Item = Event> + 'static) -> Self {
+        Sse {
+            events: Box::pin(events),
+            heartbeat: Some(Duration::from_secs(15)),
+        }
+    }
+
+    /// How long to go without an event before sending a heartbeat, `None`
+    /// to never send one.
+    pub fn heartbeat(mut self, heartbeat: Option<Duration>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// The id of the last event a reconnecting browser saw, to pick the
+    /// stream back up after it.
+    pub fn last_event_id(req: &Request) -> Option<&str> {
+        req.header("last-event-id").filter(|id| !id.is_empty())
+    }
+}
+
+impl IntoResponse for Sse {
+    fn into_response(self) -> Response {
+        let heartbeat = self.heartbeat.map(|interval| (interval, sleep(interval)));
+        let messages = Messages {
+            events: self.events,
+            heartbeat,
+        };
+        Response::builder()
+            .content_type("text/event-stream")
+            .header("cache-control", "no-cache")
+            .body(ResponseBody::Stream(Box::pin(messages)))
+    }
+}
+
+// the events of an `Sse` encoded as chunks of the body
+struct Messages {
+    events: Pin<Box<dyn Stream<Item = Event>>>,
+    heartbeat: Option<(Duration, Sleep)>,
+}
+
+impl Stream for Messages {
+    type Item = Result<Chunk>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Poll::Ready(event) = this.events.as_mut().poll_next(cx) {
+            if let Some((interval, next)) = &mut this.heartbeat {
+                *next = sleep(*interval);
+            }
+            return Poll::Ready(event.map(|event| Ok(Chunk::from(event.encode()))));
+        }
+        if let Some((interval, next)) = &mut this.heartbeat {
+            if Pin::new(&mut *next).poll(cx).is_ready() {
+                *next = sleep(*interval);
+                return Poll::Ready(Some(Ok(Chunk::from(":\n\n"))));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_event() {
+        let event = Event::new("first\nsecond").id("7").event("update");
+        assert_eq!(
+            event.encode(),
+            "id: 7\nevent: update\ndata: first\ndata: second\n\n"
+        );
+        let event = Event::new("").id("a\nb").retry(Duration::from_secs(3));
+        assert_eq!(event.encode(), "id: ab\nretry: 3000\ndata: \n\n");
+    }
+
+    #[test]
+    fn test_header_line_breaks() {
+        let res = Response::builder()
+            .status(303)
+            .header("location", "/next\r\nset-cookie: session=stolen")
+            .header("x-split\n", "a\rb")
+            .body(());
+        assert_eq!(
+            res.head(true),
+            "HTTP/1.1 303 SEE OTHER\r\n\
+             location: /nextset-cookie: session=stolen\r\n\
+             x-split: ab\r\n\
+             content-length: 0\r\n\r\n"
+        );
+    }
 }
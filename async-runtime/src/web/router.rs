@@ -1,93 +1,656 @@
-use std::collections::HashMap;
-use std::future::Future;
+use std::collections::{BTreeSet, HashMap};
 use std::io::Result;
 use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Instant;
 
 use colored::Colorize;
 
 use crate::async_io::task_queue::LocalBoxedFuture;
+use crate::async_io::timer::{timeout, timeout_at};
 use crate::async_net::client::TcpClient;
 
-use super::node::Node;
-use super::response::Response;
+use super::body::{Body, BodyKind, BodySlot};
+use super::config::{Limits, Timeouts};
+use super::connection::Connection;
+use super::extract::{Handler, StateMap};
+use super::file::File;
+use super::into_response::IntoResponse;
+use super::middleware::{Middleware, Next};
+use super::node::{Node, Params};
+use super::parser::{HeadParser, ParseError};
+use super::request::{Request, Version};
+use super::response::{status, OnUpgrade, Response, ResponseBody};
+use super::serve_dir::ServeDir;
 
-#[derive(PartialEq, Eq, Hash)]
-pub enum Method {
-    GET,
+pub use super::request::Method;
+
+pub type HandlerFn = Pin<Box<dyn Fn(Request) -> LocalBoxedFuture<'static, Response>>>;
+
+/// A handler along with the middleware that runs around it, outermost first.
+pub struct Route {
+    handler: HandlerFn,
+    middleware: Vec<Rc<dyn Middleware>>,
+    // overrides `Limits::max_body_size`
+    body_limit: Option<u64>,
 }
 
-pub type HandlerFn = Pin<Box<dyn Fn(TcpClient) -> LocalBoxedFuture<'static, Result<()>>>>;
+impl Route {
+    pub fn wrap(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware.push(Rc::new(middleware));
+        self
+    }
+
+    /// The largest request body this route takes, in place of the router's
+    /// `Limits::max_body_size`, e.g. for uploads.
+    pub fn body_limit(&mut self, limit: u64) -> &mut Self {
+        self.body_limit = Some(limit);
+        self
+    }
+
+    async fn call(&self, req: Request) -> Response {
+        let endpoint = |req| -> LocalBoxedFuture<'_, Response> { (self.handler)(req) };
+        Next::new(&self.middleware, &endpoint).run(req).await
+    }
+}
 
 pub struct Router {
-    routes: HashMap<Method, Node>,
+    routes: HashMap<Method, Node<Route>>,
+    // runs for every request, before it's routed
+    middleware: Vec<Rc<dyn Middleware>>,
+    timeouts: Timeouts,
+    limits: Limits,
+    state: Rc<StateMap>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Router {
     pub fn new() -> Self {
         Router {
             routes: HashMap::new(),
+            middleware: Vec::new(),
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            state: Rc::default(),
+        }
+    }
+
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Shares `state` with every handler, which gets a clone of it by
+    /// taking a `State<T>`. There's one value per type.
+    pub fn set_state<T: Clone + 'static>(&mut self, state: T) {
+        Rc::make_mut(&mut self.state).insert(state);
+    }
+
+    /// Adds middleware that runs for every request, including ones that
+    /// end up as a 404 or 405.
+    pub fn wrap(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware.push(Rc::new(middleware));
+        self
+    }
+
+    /// Routes under a common prefix that share middleware.
+    pub fn group(&mut self, prefix: &str) -> Group<'_> {
+        Group {
+            router: self,
+            prefix: String::from(prefix.trim_end_matches('/')),
+            middleware: Vec::new(),
         }
     }
 
-    pub fn insert<F, Fut>(&mut self, method: Method, path: &str, handler: F)
+    /// Serves the files under a directory at `prefix`, e.g.
+    /// `serve_dir("/static", "static/")`.
+    pub fn serve_dir(&mut self, prefix: &str, dir: impl Into<ServeDir>) -> &mut Route {
+        let dir = Rc::new(dir.into());
+        let path = format!("{}/*path", prefix.trim_end_matches('/'));
+        self.insert(Method::GET, &path, move |req: Request| {
+            let res = match dir.serve(&req) {
+                // a miss looks the same as any other path with nothing behind it
+                res if res.status == 404 => not_found_page(),
+                res => res,
+            };
+            async move { res }
+        })
+    }
+
+    /// Adds a handler for `method` requests to `path`. It can be any async
+    /// function taking extractors, see `Handler`.
+    pub fn insert<H, Args>(&mut self, method: Method, path: &str, handler: H) -> &mut Route
     where
-        F: Fn(TcpClient) -> Fut + 'static,
-        Fut: Future<Output = Result<()>> + 'static,
+        H: Handler<Args>,
     {
+        let handler = Rc::new(handler);
+        let route = Route {
+            handler: Box::pin(move |req| {
+                let handler = handler.clone();
+                Box::pin(async move { handler.call(req).await })
+            }),
+            middleware: Vec::new(),
+            body_limit: None,
+        };
         let node = self.routes.entry(method).or_insert(Node::new("/"));
-        node.insert(path, Box::pin(move |client| Box::pin(handler(client))));
+        node.insert(path, route)
     }
 
-    pub async fn route_client(&mut self, mut client: TcpClient) -> Result<()> {
-        let mut buffer = [0; 1024];
-        let n = client.read(&mut buffer).await?;
+    /// Serves requests off of one connection in the order they arrive until
+    /// either side asks to close it, it sits idle too long or it reaches
+    /// `max_requests`.
+    pub async fn route_client(&self, client: TcpClient) -> Result<()> {
+        let mut conn = Connection::new(client);
+        let mut served = 0;
+        loop {
+            // pipelined requests may already be waiting in the buffer
+            if conn.buffered().is_empty() {
+                let n = match timeout(self.timeouts.keep_alive, conn.fill()).await {
+                    Ok(n) => n?,
+                    Err(_) => {
+                        println!(
+                            "{} {:?} closing idle connection",
+                            format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                            std::thread::current().id(),
+                        );
+                        return Ok(());
+                    }
+                };
+                if n == 0 {
+                    return Ok(());
+                }
+            }
+
+            served += 1;
+            let last = served >= self.limits.max_requests;
+            conn = match self.respond(conn, last).await? {
+                Some(conn) => conn,
+                None => return Ok(()),
+            };
+        }
+    }
+
+    // hands back the connection if it can be kept open for another request
+    async fn respond(&self, mut conn: Connection, last: bool) -> Result<Option<Connection>> {
+        let request_deadline = Instant::now() + self.timeouts.request;
+        let deadline = Instant::now() + self.timeouts.header_read;
+        let mut parser = HeadParser::new(&self.limits);
+        let mut request = loop {
+            match parser.parse(conn.buffered()) {
+                Ok(Some((request, len))) => {
+                    conn.consume(len);
+                    break request;
+                }
+                Ok(None) => match timeout_at(deadline, conn.fill()).await {
+                    Ok(read) => {
+                        if read? == 0 {
+                            return Ok(None);
+                        }
+                    }
+                    Err(_) => {
+                        self.close_with(conn.into_client(), self.request_timeout())
+                            .await?;
+                        return Ok(None);
+                    }
+                },
+                Err(e) => {
+                    self.close_with(conn.into_client(), self.parse_error(e))
+                        .await?;
+                    return Ok(None);
+                }
+            }
+        };
 
-        // read a single line (if one exists)
-        let req = String::from_utf8_lossy(&buffer[0..n]);
-        let mut lines = req.split('\n');
-        let line = lines.next().unwrap();
         println!(
-            "{} {:?} client requested\n{}",
+            "{} {:?} client requested\n{} {} {}",
             format!("[{}]", std::process::id()).truecolor(0, 255, 136),
             std::thread::current().id(),
-            &line
+            request.method,
+            request.target,
+            request.version
         );
 
-        // consume bytes read from original reader
-        let parts: Vec<&str> = line.split(" ").collect();
-        if parts.len() < 2 {
-            self.bad_request(client).await
+        let version = request.version;
+        let mut keep_alive = !last
+            && match version {
+                Version::Http11 => !request.headers.has_token("connection", "close"),
+                Version::Http10 => request.headers.has_token("connection", "keep-alive"),
+            };
+
+        let mut client = conn.client().try_clone()?;
+        client.set_write_timeout(Some(self.timeouts.write));
+        let head_only = request.method == Method::HEAD;
+
+        // the handler reads the body off the connection, which comes back
+        // through the slot when it drops the request
+        let slot = BodySlot::default();
+        let mut body = Body::new(request.body_kind(), conn, self.timeouts.body_read);
+        body.return_to(slot.clone());
+        body.set_limit(self.limits.max_body_size);
+        request.set_body(body);
+        let mut res = match timeout_at(request_deadline, self.handle(request)).await {
+            Ok(res) => res,
+            // nothing has been written yet, so there's still time to say why
+            Err(_) => {
+                self.close_with(client, self.request_timeout()).await?;
+                return Ok(None);
+            }
+        };
+        let upgrade = match std::mem::take(&mut res.body) {
+            ResponseBody::Upgrade(on_upgrade) if res.status == 101 => Some(on_upgrade),
+            body => {
+                res.body = body;
+                None
+            }
+        };
+        if res.status == 413 {
+            // the rest of a body that's too big isn't worth reading
+            keep_alive = false;
+        }
+        if res.body.is_stream() && version == Version::Http10 {
+            // nothing but closing the connection can end the body
+            keep_alive = false;
+        }
+        // a switch of protocols brings its own `connection: upgrade`
+        if upgrade.is_none() {
+            res.set_header(
+                "connection",
+                if keep_alive { "keep-alive" } else { "close" },
+            );
+        }
+        if res.body.is_stream() {
+            // a stream is sent for as long as it has something to say
+            res.write(&mut client, head_only, version).await?;
         } else {
-            match (parts[0], parts[1]) {
-                ("GET", path) => self.handle(Method::GET, path, client).await,
-                _ => self.not_found(client).await,
+            timeout_at(request_deadline, res.write(&mut client, head_only, version)).await??;
+        }
+
+        let body = slot.borrow_mut().take();
+        if let Some(on_upgrade) = upgrade {
+            return self.upgrade(body, on_upgrade).await;
+        }
+        let mut body = match body {
+            Some(body) if keep_alive => body,
+            // closing anyway, or a task spawned by the handler still has the request
+            _ => return Ok(None),
+        };
+        // whatever the handler left unread has to go before the next request
+        if let Err(e) = body.drain(self.limits.max_body_size).await {
+            println!(
+                "{} {:?} closing connection, unread body: {}",
+                format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                std::thread::current().id(),
+                e
+            );
+            return Ok(None);
+        }
+        Ok(body.into_connection())
+    }
+
+    // hands the connection over to the protocol a `101` switched it to, it's
+    // never used for HTTP again
+    async fn upgrade(
+        &self,
+        body: Option<Body>,
+        on_upgrade: OnUpgrade,
+    ) -> Result<Option<Connection>> {
+        let mut body = match body {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+        body.drain(self.limits.max_body_size).await?;
+        if let Some(mut conn) = body.into_connection() {
+            conn.client_mut()
+                .set_write_timeout(Some(self.timeouts.write));
+            println!(
+                "{} {:?} upgrading connection",
+                format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                std::thread::current().id(),
+            );
+            on_upgrade(conn).await;
+        }
+        Ok(None)
+    }
+
+    pub async fn handle(&self, mut req: Request) -> Response {
+        req.set_state(self.state.clone());
+        let endpoint = |req| -> LocalBoxedFuture<'_, Response> { Box::pin(self.dispatch(req)) };
+        Next::new(&self.middleware, &endpoint).run(req).await
+    }
+
+    async fn dispatch(&self, mut req: Request) -> Response {
+        let found = self.find(&req.method, req.path()).or_else(|| {
+            // GET handlers answer HEAD too, the router just leaves out the body
+            if req.method == Method::HEAD {
+                self.find(&Method::GET, req.path())
+            } else {
+                None
+            }
+        });
+        if let Some((route, params)) = found {
+            let limit = route.body_limit.unwrap_or(self.limits.max_body_size);
+            if let BodyKind::Length(len) = req.body_kind() {
+                if len > limit {
+                    return self.payload_too_large();
+                }
             }
+            req.body().set_limit(limit);
+            req.params = params;
+            return route.call(req).await;
         }
+
+        let allowed = self.allowed(req.path());
+        if allowed.is_empty() {
+            return self.not_found();
+        }
+        let allow: Vec<&str> = allowed.iter().map(|m| m.as_str()).collect();
+        let mut res = if req.method == Method::OPTIONS {
+            Response::new(204, ())
+        } else {
+            self.method_not_allowed()
+        };
+        res.set_header("allow", &allow.join(", "));
+        res
+    }
+
+    fn find(&self, method: &Method, resource: &str) -> Option<(&Route, Params)> {
+        self.routes.get(method).and_then(|node| node.get(resource))
+    }
+
+    // methods with a handler for `resource`, or for any resource given `*`
+    fn allowed(&self, resource: &str) -> BTreeSet<Method> {
+        let mut allowed: BTreeSet<Method> = self
+            .routes
+            .iter()
+            .filter(|(_, node)| resource == "*" || node.get(resource).is_some())
+            .map(|(method, _)| method.clone())
+            .collect();
+        if allowed.is_empty() {
+            return allowed;
+        }
+        if allowed.contains(&Method::GET) {
+            allowed.insert(Method::HEAD);
+        }
+        allowed.insert(Method::OPTIONS);
+        allowed
+    }
+
+    pub fn bad_request(&self) -> Response {
+        (400, File::new("static/_400.html")).into_response()
     }
 
-    pub async fn handle(
-        &mut self,
-        method: Method,
-        resource: &str,
-        client: TcpClient,
-    ) -> Result<()> {
-        if let Some(node) = self.routes.get_mut(&method) {
-            if let Some(handler) = node.get(resource) {
-                return handler(client).await;
+    pub fn not_found(&self) -> Response {
+        not_found_page()
+    }
+
+    pub fn method_not_allowed(&self) -> Response {
+        (405, File::new("static/_405.html")).into_response()
+    }
+
+    pub fn parse_error(&self, error: ParseError) -> Response {
+        println!(
+            "{} {:?} rejecting request, {}",
+            format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+            std::thread::current().id(),
+            error
+        );
+        match error {
+            ParseError::BadRequest(_) => self.bad_request(),
+            _ => {
+                let code = error.status();
+                (code, format!("{} {}\n", code, status(code))).into_response()
             }
         }
+    }
+
+    pub fn payload_too_large(&self) -> Response {
+        (413, "413 PAYLOAD TOO LARGE\n").into_response()
+    }
+
+    pub fn request_timeout(&self) -> Response {
+        (408, File::new("static/_408.html")).into_response()
+    }
+
+    pub async fn service_unavailable(&self, client: TcpClient) -> Result<()> {
+        let res = (503, File::new("static/_503.html")).into_response();
+        self.close_with(client, res).await
+    }
 
-        // default not found
-        self.bad_request(client).await
+    // sends a response that ends the connection
+    async fn close_with(&self, mut client: TcpClient, mut res: Response) -> Result<()> {
+        res.set_header("connection", "close");
+        // the request may not have been read far enough to know its version
+        res.write(&mut client, false, Version::Http11).await
+    }
+}
+
+/// A set of routes under one prefix. Middleware added with `wrap` applies to
+/// the routes inserted after it, and runs before any of their own.
+pub struct Group<'r> {
+    router: &'r mut Router,
+    prefix: String,
+    middleware: Vec<Rc<dyn Middleware>>,
+}
+
+impl Group<'_> {
+    pub fn wrap(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware.push(Rc::new(middleware));
+        self
     }
 
-    pub async fn bad_request(&self, client: TcpClient) -> Result<()> {
-        let mut res = Response::new(client);
-        res.send_file(400, "static/_400.html").await
+    /// A nested group that starts out with this group's middleware.
+    pub fn group(&mut self, prefix: &str) -> Group<'_> {
+        Group {
+            prefix: format!("{}{}", self.prefix, prefix.trim_end_matches('/')),
+            middleware: self.middleware.clone(),
+            router: self.router,
+        }
     }
 
-    pub async fn not_found(&self, client: TcpClient) -> Result<()> {
-        let mut res = Response::new(client);
-        res.send_file(404, "static/_404.html").await
+    pub fn insert<H, Args>(&mut self, method: Method, path: &str, handler: H) -> &mut Route
+    where
+        H: Handler<Args>,
+    {
+        let path = format!("{}{}", self.prefix, path);
+        let route = self.router.insert(method, &path, handler);
+        route.middleware = self.middleware.clone();
+        route
+    }
+}
+
+fn not_found_page() -> Response {
+    (404, File::new("static/_404.html")).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::async_io::timer::sleep;
+    use crate::web::response::Chunk;
+    use crate::web::test_util::{block_on, request, run};
+
+    // serves a single connection, with `client` on the other end of it on
+    // another thread, and hands back how serving it ended along with what
+    // the client returned
+    fn serve<F, T>(router: Router, client: F) -> (Result<()>, T)
+    where
+        F: FnOnce(net::TcpStream) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let client = thread::spawn(move || client(peer));
+
+        let served = block_on(async move { router.route_client(TcpClient::new(stream)).await });
+        (served, client.join().unwrap())
+    }
+
+    // everything sent back until the server closes the connection
+    fn read_all(mut stream: net::TcpStream) -> String {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut response = Vec::new();
+        match stream.read_to_end(&mut response) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::ConnectionReset => {}
+            Err(e) => panic!("{}", e),
+        }
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    fn send(request: &'static str) -> impl FnOnce(net::TcpStream) -> String {
+        move |mut stream| {
+            stream.write_all(request.as_bytes()).unwrap();
+            read_all(stream)
+        }
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router.set_timeouts(Timeouts {
+            header_read: Duration::from_millis(100),
+            body_read: Duration::from_millis(100),
+            keep_alive: Duration::from_millis(100),
+            request: Duration::from_millis(300),
+            write: Duration::from_millis(100),
+        });
+        router.insert(Method::GET, "/", || async { "home" });
+        router.insert(Method::POST, "/echo", |mut body: Body| async move {
+            let mut data = Vec::new();
+            body.read_to_end(&mut data, 1024).await.map(|_| data)
+        });
+        router.insert(Method::GET, "/slow", || async {
+            sleep(Duration::from_secs(5)).await;
+            "too late"
+        });
+        router.insert(Method::GET, "/flood", || async {
+            let chunks = crate::async_io::stream::unfold((), |_| async {
+                Some((Ok(Chunk::Data(vec![b'x'; 64 * 1024])), ()))
+            });
+            ResponseBody::stream(chunks)
+        });
+        router
+    }
+
+    #[test]
+    fn test_timeouts() {
+        // an idle connection is closed without a word
+        let (served, response) = serve(router(), read_all);
+        assert!(served.is_ok());
+        assert_eq!(response, "");
+
+        // a head that never ends
+        let (_, response) = serve(router(), send("GET / HTTP/1.1\r\nhost: a\r\n"));
+        assert!(response.starts_with("HTTP/1.1 408"));
+
+        // a body that stops short of its length
+        let request = "POST /echo HTTP/1.1\r\nhost: a\r\ncontent-length: 10\r\n\r\nab";
+        let (_, response) = serve(router(), send(request));
+        assert!(response.starts_with("HTTP/1.1 408"));
+
+        // a handler that takes longer than the whole request may
+        let (_, response) = serve(router(), send("GET /slow HTTP/1.1\r\nhost: a\r\n\r\n"));
+        assert!(response.starts_with("HTTP/1.1 408"));
+        assert!(response.contains("connection: close"));
+
+        // a client that never reads a streamed body
+        let (served, _) = serve(router(), |mut stream| {
+            stream
+                .write_all(b"GET /flood HTTP/1.1\r\nhost: a\r\n\r\n")
+                .unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+        assert_eq!(served.unwrap_err().kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_methods() {
+        // a method the path doesn't have is told which ones it does
+        let request = "DELETE /echo HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n";
+        let (_, response) = serve(router(), send(request));
+        assert!(response.starts_with("HTTP/1.1 405"));
+        assert!(response.contains("\r\nallow: POST, OPTIONS\r\n"));
+
+        // HEAD is answered by the GET handler, without the body but with
+        // the length it would have had
+        let request = "HEAD / HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n";
+        let (_, response) = serve(router(), send(request));
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\r\ncontent-length: 4\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+
+        // OPTIONS lists them all, HEAD included wherever there's a GET
+        let request = "OPTIONS / HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n";
+        let (_, response) = serve(router(), send(request));
+        assert!(response.starts_with("HTTP/1.1 204"));
+        assert!(response.contains("\r\nallow: GET, HEAD, OPTIONS\r\n"));
+    }
+
+    #[test]
+    fn test_pipelining() {
+        // requests sent back to back are answered in the order they came
+        let request = "GET / HTTP/1.1\r\nhost: a\r\n\r\n\
+            POST /echo HTTP/1.1\r\nhost: a\r\ncontent-length: 4\r\nconnection: close\r\n\r\necho";
+        let (served, response) = serve(router(), send(request));
+        assert!(served.is_ok());
+        let (first, second) = response.split_once("\r\n\r\nhome").unwrap();
+        assert!(first.starts_with("HTTP/1.1 200"));
+        assert!(second.starts_with("HTTP/1.1 200"));
+        assert!(second.ends_with("\r\n\r\necho"));
+
+        // nothing after a `connection: close` is read
+        let request = "GET / HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n\
+            GET / HTTP/1.1\r\nhost: a\r\n\r\n";
+        let (_, response) = serve(router(), send(request));
+        assert_eq!(response.matches("HTTP/1.1 200").count(), 1);
+        assert!(response.contains("\r\nconnection: close\r\n"));
+
+        // nor past the last request a connection may serve
+        let mut router = router();
+        router.set_limits(Limits {
+            max_requests: 2,
+            ..Limits::default()
+        });
+        let request = "GET / HTTP/1.1\r\nhost: a\r\n\r\n\
+            GET / HTTP/1.1\r\nhost: a\r\n\r\n\
+            GET / HTTP/1.1\r\nhost: a\r\n\r\n";
+        let (_, response) = serve(router, send(request));
+        assert_eq!(response.matches("HTTP/1.1 200").count(), 2);
+        let (_, last) = response.rsplit_once("HTTP/1.1 200").unwrap();
+        assert!(last.contains("\r\nconnection: close\r\n"));
+    }
+
+    #[test]
+    fn test_serve_dir_miss() {
+        let mut router = Router::new();
+        router.serve_dir("/static", "static/");
+        let router = Rc::new(router);
+        let missing = run(
+            &router,
+            request(Method::GET, "/static/missing.txt", &[], b""),
+        );
+        let unknown = run(&router, request(Method::GET, "/missing.txt", &[], b""));
+        assert_eq!(missing.status, 404);
+        assert_eq!(
+            missing.header("content-type"),
+            unknown.header("content-type")
+        );
+        assert_eq!(missing.body.len(), unknown.body.len());
     }
 }
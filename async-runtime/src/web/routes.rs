@@ -1,23 +1,247 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::async_io::{stream, timer::sleep};
+
 use super::{
-    response::Response,
+    body::Body,
+    cache::{CacheControl, ConditionalGet},
+    compress::Compression,
+    extract::{Path, State},
+    file::File,
+    form::{Form, FormRejection, Multipart},
+    headers::Headers,
+    into_response::{IntoResponse, Redirect},
+    json::Json,
+    query::Query,
+    range::Ranges,
+    request::Request,
+    response::{Chunk, Event, Response, ResponseBody, Sse},
     router::{Method, Router},
+    serve_dir::ServeDir,
+    websocket::{Message, WebSocketUpgrade},
 };
 
 pub fn configure(router: &mut Router) {
-    router.insert(Method::GET, "/", |client| async {
-        let mut res = Response::new(client);
-        res.send_file(200, "static/index.html").await
-    });
-    router.insert(Method::GET, "/todo", |client| async {
-        let mut res = Response::new(client);
-        res.send_file(200, "static/todo.html").await
+    // ranges are taken out of the response after a 304 has had its chance
+    router.wrap(Ranges);
+    router.wrap(ConditionalGet);
+    // compressed bodies carry their own etag, which the outer two then see
+    router.wrap(Compression::new());
+
+    // pages are revalidated on every load, assets are cached for an hour
+    router
+        .insert(Method::GET, "/", || async {
+            File::new("static/index.html")
+        })
+        .wrap(CacheControl::new("no-cache"));
+    router
+        .insert(Method::GET, "/todo", || async {
+            File::new("static/todo.html")
+        })
+        .wrap(CacheControl::new("no-cache"));
+    router.serve_dir(
+        "/static",
+        ServeDir::new("static/").cache_control("public, max-age=3600"),
+    );
+    todo_api(router);
+    router.insert(Method::GET, "/favicon.ico", || async {
+        File::new("static/favicon.ico")
     });
-    router.insert(Method::GET, "/static/styles.css", |client| async {
-        let mut res = Response::new(client);
-        res.send_file(200, "static/styles.css").await
+    router
+        .insert(Method::GET, "/echo", || async {
+            File::new("static/echo.html")
+        })
+        .wrap(CacheControl::new("no-cache"));
+    router
+        .insert(Method::GET, "/upload", || async {
+            File::new("static/upload.html")
+        })
+        .wrap(CacheControl::new("no-cache"));
+    router
+        .insert(Method::POST, "/upload", upload)
+        .body_limit(32 * 1024 * 1024);
+    router.insert(Method::GET, "/echo/ws", echo_socket);
+    router.insert(Method::POST, "/echo", echo);
+    router.insert(Method::GET, "/stream", count_lines);
+    router.insert(Method::GET, "/todo/events", todo_events);
+}
+
+// reports what was uploaded without keeping any of it
+async fn upload(mut form: Multipart) -> Result<Json<Vec<Upload>>, FormRejection> {
+    let mut parts = Vec::new();
+    while let Some(mut part) = form.next_part().await? {
+        let mut upload = Upload {
+            name: String::from(part.name()),
+            filename: part.filename().map(String::from),
+            size: 0,
+        };
+        while let Some(data) = part.chunk().await? {
+            upload.size += data.len() as u64;
+        }
+        parts.push(upload);
+    }
+    Ok(Json(parts))
+}
+
+// sends every message straight back until the page closes the socket
+async fn echo_socket(upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(|mut socket| async move {
+        while let Ok(Some(message)) = socket.recv().await {
+            let echo = matches!(message, Message::Text(_) | Message::Binary(_));
+            if echo && socket.send(message).await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+async fn echo(mut body: Body) -> std::io::Result<Response> {
+    let mut data = Vec::new();
+    body.read_to_end(&mut data, 64 * 1024).await?;
+    Ok(Response::builder()
+        .content_type("text/plain; charset=UTF-8")
+        .body(data))
+}
+
+// counts to five, a line every 200ms, and ends with the total in a trailer
+async fn count_lines() -> Response {
+    let lines = stream::unfold(1, |n| async move {
+        if n > 6 {
+            return None;
+        }
+        let chunk = if n > 5 {
+            let mut trailers = Headers::new();
+            trailers.insert("x-line-count", "5");
+            Chunk::Trailers(trailers)
+        } else {
+            sleep(Duration::from_millis(200)).await;
+            Chunk::Data(format!("line {}\n", n).into_bytes())
+        };
+        Some((Ok(chunk), n + 1))
     });
-    router.insert(Method::GET, "/favicon.ico", |client| async {
-        let mut res = Response::new(client);
-        res.send_file(200, "static/favicon.ico").await
+    Response::builder()
+        .content_type("text/plain; charset=UTF-8")
+        .header("trailer", "x-line-count")
+        .body(ResponseBody::stream(lines))
+}
+
+// live updates for the todo page, picking up after the last one a
+// reconnecting page saw
+async fn todo_events(req: Request) -> Sse {
+    let next = Sse::last_event_id(&req)
+        .and_then(|id| id.parse::<u64>().ok())
+        .map_or(1, |id| id + 1);
+    let updates = stream::unfold(next, |n| async move {
+        sleep(Duration::from_secs(2)).await;
+        let event = Event::new(format!("update {}", n))
+            .id(n.to_string())
+            .event("update");
+        Some((event, n + 1))
     });
+    Sse::new(updates)
+}
+
+#[derive(Serialize)]
+struct Upload {
+    name: String,
+    filename: Option<String>,
+    size: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct Todo {
+    id: u64,
+    title: String,
+    done: bool,
+}
+
+#[derive(Deserialize)]
+struct NewTodo {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct TodoFilter {
+    done: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct TodoUpdate {
+    title: Option<String>,
+    done: Option<bool>,
+}
+
+// the todo page's list, kept in memory for as long as the worker runs
+#[derive(Clone, Default)]
+struct Todos(Rc<RefCell<Vec<Todo>>>);
+
+impl Todos {
+    fn add(&self, title: String) -> Todo {
+        let mut todos = self.0.borrow_mut();
+        let todo = Todo {
+            id: todos.last().map_or(1, |todo| todo.id + 1),
+            title,
+            done: false,
+        };
+        todos.push(todo.clone());
+        todo
+    }
+}
+
+fn todo_api(router: &mut Router) {
+    router.set_state(Todos::default());
+    router.insert(Method::GET, "/api/todos", list_todos);
+    router.insert(Method::POST, "/todo", submit_todo);
+    router.insert(Method::POST, "/api/todos", create_todo);
+    router.insert(Method::PATCH, "/api/todos/{id}", update_todo);
+}
+
+// `?done=true` or `?done=false` narrows the list down
+async fn list_todos(
+    State(todos): State<Todos>,
+    Query(filter): Query<TodoFilter>,
+) -> Json<Vec<Todo>> {
+    let todos = todos
+        .0
+        .borrow()
+        .iter()
+        .filter(|todo| filter.done.is_none_or(|done| todo.done == done))
+        .cloned()
+        .collect();
+    Json(todos)
+}
+
+// the page's form posts here when scripts are off
+async fn submit_todo(State(todos): State<Todos>, Form(new): Form<NewTodo>) -> Redirect {
+    todos.add(new.title);
+    Redirect::to("/todo")
+}
+
+async fn create_todo(State(todos): State<Todos>, Json(new): Json<NewTodo>) -> Response {
+    let todo = todos.add(new.title);
+    let location = format!("/api/todos/{}", todo.id);
+    (201, [("location", location.as_str())], Json(todo)).into_response()
+}
+
+async fn update_todo(
+    State(todos): State<Todos>,
+    Path(id): Path<u64>,
+    Json(update): Json<TodoUpdate>,
+) -> Response {
+    let mut todos = todos.0.borrow_mut();
+    let todo = match todos.iter_mut().find(|todo| todo.id == id) {
+        Some(todo) => todo,
+        None => return (404, "404 NOT FOUND\n").into_response(),
+    };
+    if let Some(title) = update.title {
+        todo.title = title;
+    }
+    if let Some(done) = update.done {
+        todo.done = done;
+    }
+    Json(todo.clone()).into_response()
 }
@@ -0,0 +1,188 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::compress::{self, Encoding};
+use super::file::File;
+use super::into_response::IntoResponse;
+use super::mime::MimeTypes;
+use super::request::Request;
+use super::response::Response;
+use super::url::percent_decode;
+
+/// Serves the files under a directory, mounted with `Router::serve_dir`.
+/// Requests for a directory get its `index.html`.
+#[derive(Clone, Debug)]
+pub struct ServeDir {
+    root: PathBuf,
+    mime_types: MimeTypes,
+    cache_control: Option<String>,
+    precompressed: bool,
+}
+
+impl ServeDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ServeDir {
+            root: root.into(),
+            mime_types: MimeTypes::default(),
+            cache_control: None,
+            precompressed: true,
+        }
+    }
+
+    /// Adds or overrides the MIME type served for an extension.
+    pub fn mime_type(mut self, ext: &str, mime_type: &str) -> Self {
+        self.mime_types.insert(ext, mime_type);
+        self
+    }
+
+    /// The `Cache-Control` sent with every file in the directory.
+    pub fn cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = Some(String::from(cache_control));
+        self
+    }
+
+    /// Whether to send `file.br` or `file.gz` from next to a requested file
+    /// when the client accepts that coding. On by default.
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Answers a request whose `path` parameter is relative to the root.
+    pub fn serve(&self, req: &Request) -> Response {
+        let path = req.param("path").unwrap_or_default();
+        match self.resolve(path) {
+            Ok(file) => {
+                let mime_type = self.mime_types.get(&file.to_string_lossy());
+                let encoded = req
+                    .header("accept-encoding")
+                    .filter(|_| self.precompressed)
+                    .and_then(|accept| self.precompressed_sibling(&file, accept));
+                let (path, encoding) = match encoded {
+                    Some((path, encoding)) => (path, Some(encoding)),
+                    None => (file, None),
+                };
+
+                let mut file = File::new(path).with_mime_type(mime_type);
+                if let Some(cache_control) = &self.cache_control {
+                    file = file.with_cache_control(cache_control);
+                }
+                let mut res = file.into_response();
+                if let Some(encoding) = encoding {
+                    res.set_header("content-encoding", encoding.name());
+                }
+                if self.precompressed {
+                    compress::vary_on_encoding(&mut res);
+                }
+                res
+            }
+            // don't tell apart missing files from ones outside of the root
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                io::Error::from(ErrorKind::NotFound).into_response()
+            }
+            Err(e) => e.into_response(),
+        }
+    }
+
+    /// Maps a request path onto a file under the root, refusing anything
+    /// that would end up outside of it, through `..` or a symlink.
+    pub fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let mut file = self.root.clone();
+        for segment in path.split('/') {
+            let segment = percent_decode(segment).ok_or(ErrorKind::InvalidData)?;
+            match segment.as_str() {
+                "" | "." => continue,
+                ".." => return Err(ErrorKind::InvalidData.into()),
+                s if s.contains(['/', '\0']) => return Err(ErrorKind::InvalidData.into()),
+                s => file.push(s),
+            }
+        }
+
+        let file = self.contain(file)?;
+        if file.is_dir() {
+            return self.contain(file.join("index.html"));
+        }
+        Ok(file)
+    }
+
+    /// The best precompressed copy of a resolved file the client accepts.
+    fn precompressed_sibling(&self, file: &Path, accept: &str) -> Option<(PathBuf, Encoding)> {
+        let sibling = |encoding: &Encoding| {
+            let mut path = file.as_os_str().to_owned();
+            path.push(".");
+            path.push(encoding.extension());
+            PathBuf::from(path)
+        };
+        let available: Vec<Encoding> = [Encoding::Brotli, Encoding::Gzip]
+            .into_iter()
+            .filter(|encoding| sibling(encoding).is_file())
+            .collect();
+        let encoding = compress::negotiate(accept, &available)?;
+        let path = self.contain(sibling(&encoding)).ok()?;
+        Some((path, encoding))
+    }
+
+    fn contain(&self, path: PathBuf) -> io::Result<PathBuf> {
+        let root = fs::canonicalize(&self.root)?;
+        let path = fs::canonicalize(path)?;
+        if !path.starts_with(&root) {
+            return Err(ErrorKind::PermissionDenied.into());
+        }
+        Ok(path)
+    }
+}
+
+impl From<&str> for ServeDir {
+    fn from(root: &str) -> Self {
+        ServeDir::new(root)
+    }
+}
+
+impl From<&Path> for ServeDir {
+    fn from(root: &Path) -> Self {
+        ServeDir::new(root)
+    }
+}
+
+impl From<PathBuf> for ServeDir {
+    fn from(root: PathBuf) -> Self {
+        ServeDir::new(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let base = std::env::temp_dir().join(format!("serve-dir-{}", std::process::id()));
+        let root = base.join("public");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/index.html"), "index").unwrap();
+        fs::write(root.join("a b.txt"), "spaced").unwrap();
+        fs::write(base.join("secret.txt"), "secret").unwrap();
+        symlink(base.join("secret.txt"), root.join("escape.txt")).unwrap();
+
+        let dir = ServeDir::new(&root);
+        let root = fs::canonicalize(&root).unwrap();
+        assert_eq!(dir.resolve("a%20b.txt").unwrap(), root.join("a b.txt"));
+        assert_eq!(dir.resolve("docs/").unwrap(), root.join("docs/index.html"));
+        assert_eq!(
+            dir.resolve("./docs//index.html").unwrap(),
+            root.join("docs/index.html")
+        );
+
+        let kind = |path| dir.resolve(path).unwrap_err().kind();
+        assert_eq!(kind("../secret.txt"), ErrorKind::InvalidData);
+        assert_eq!(kind("docs/%2e%2e/../secret.txt"), ErrorKind::InvalidData);
+        assert_eq!(kind("docs%2f..%2f..%2fsecret.txt"), ErrorKind::InvalidData);
+        assert_eq!(kind("escape.txt"), ErrorKind::PermissionDenied);
+        assert_eq!(kind("missing.txt"), ErrorKind::NotFound);
+
+        fs::remove_dir_all(base).unwrap();
+    }
+}
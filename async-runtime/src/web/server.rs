@@ -0,0 +1,284 @@
+use std::cell::Cell;
+use std::io::Result;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use colored::Colorize;
+
+use crate::async_io::select::{select, Either};
+use crate::async_io::shutdown::Shutdown;
+use crate::async_io::stream::StreamExt;
+use crate::async_io::timer::{sleep, timeout};
+use crate::async_net::client::TcpClient;
+use crate::async_net::listener::TcpListener;
+
+use super::router::Router;
+
+// how long to wait before accepting again after accept itself failed
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+// how long a client turned away with a 503 gets to take it
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+// past this many 503s still being written, new connections are just closed
+const MAX_REJECTING: usize = 64;
+
+/// What to do with new connections once `max_connections` is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Stop accepting until a connection closes, clients wait in the backlog.
+    Pause,
+    /// Keep accepting but answer immediately with a 503 and close.
+    Reject,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub max_connections: Option<usize>,
+    pub overflow: Overflow,
+    // how long in-flight connections get to finish once shutdown starts
+    pub shutdown_deadline: Duration,
+    // share the address with other executor threads via SO_REUSEPORT
+    pub reuse_port: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            max_connections: None,
+            overflow: Overflow::Pause,
+            shutdown_deadline: Duration::from_secs(10),
+            reuse_port: false,
+        }
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    config: ServerConfig,
+    shutdown: Shutdown,
+    // connections being answered with a 503, tracked by `shutdown` like
+    // the rest but not counted against `max_connections`
+    rejecting: Rc<Cell<usize>>,
+}
+
+impl Server {
+    pub fn bind(addr: &str, config: ServerConfig) -> Result<Server> {
+        let listener = if config.reuse_port {
+            TcpListener::bind_reuse_port(addr)?
+        } else {
+            TcpListener::bind(addr)?
+        };
+        Ok(Server {
+            listener,
+            config,
+            shutdown: Shutdown::new(),
+            rejecting: Rc::new(Cell::new(0)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    /// Accepts connections until shutdown is triggered, then drains them.
+    pub async fn serve(self, router: Router) {
+        let router = Rc::new(router);
+        let mut incoming = self.listener.incoming();
+        loop {
+            if self.at_capacity() && self.config.overflow == Overflow::Pause {
+                println!(
+                    "{} {:?} connection limit reached, pausing accept",
+                    format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                    std::thread::current().id()
+                );
+                incoming.pause();
+                let max = self.config.max_connections.unwrap();
+                match select(self.shutdown.below(max), self.shutdown.triggered()).await {
+                    Either::Left(_) => continue,
+                    Either::Right(_) => break,
+                }
+            }
+
+            match select(incoming.next(), self.shutdown.triggered()).await {
+                Either::Left(Some(Ok((client, _addr)))) => {
+                    // checked again now, a connection may have closed while
+                    // this one waited to be accepted
+                    if self.at_capacity() {
+                        self.reject(&router, client);
+                        continue;
+                    }
+                    let router = router.clone();
+                    self.shutdown.spawn(async move {
+                        if let Err(e) = router.route_client(client).await {
+                            println!("client connection failed: {}", e);
+                        }
+                    });
+                }
+                Either::Left(Some(Err(e))) => {
+                    // most likely out of file descriptors, which frees up as
+                    // connections close, so keep going after a pause
+                    println!(
+                        "{} {:?} accept failed, {}",
+                        format!("[{}]", std::process::id()).truecolor(0, 255, 136),
+                        std::thread::current().id(),
+                        e
+                    );
+                    if let Either::Right(_) =
+                        select(sleep(ACCEPT_BACKOFF), self.shutdown.triggered()).await
+                    {
+                        break;
+                    }
+                }
+                Either::Left(None) | Either::Right(_) => break,
+            }
+        }
+
+        // stop accepting before we wait on in-flight connections
+        drop(incoming);
+        drop(self.listener);
+        self.shutdown.drain(self.config.shutdown_deadline).await;
+    }
+
+    fn at_capacity(&self) -> bool {
+        let in_flight = self.shutdown.in_flight() - self.rejecting.get();
+        self.config
+            .max_connections
+            .is_some_and(|max| in_flight >= max)
+    }
+
+    // answers with a 503 on a task of its own so a slow client can't hold
+    // up accepting, and with a timeout so it can't hold up the drain
+    fn reject(&self, router: &Rc<Router>, client: TcpClient) {
+        if self.rejecting.get() >= MAX_REJECTING {
+            return;
+        }
+        self.rejecting.set(self.rejecting.get() + 1);
+        let rejecting = self.rejecting.clone();
+        let router = router.clone();
+        self.shutdown.spawn(async move {
+            match timeout(REJECT_TIMEOUT, router.service_unavailable(client)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => println!("client connection failed: {}", e),
+                Err(_) => println!("client connection failed: 503 timed out"),
+            }
+            rejecting.set(rejecting.get() - 1);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::async_io::executor;
+    use crate::web::router::Method;
+
+    // what the server sent back before closing the connection, or before
+    // going quiet for 300ms
+    fn read_response(stream: &mut TcpStream) -> String {
+        stream
+            .set_read_timeout(Some(Duration::from_millis(300)))
+            .unwrap();
+        let mut response = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => response.extend_from_slice(&buf[..n]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    fn get(addr: SocketAddr, connection: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = format!(
+            "GET / HTTP/1.1\r\nhost: localhost\r\nconnection: {}\r\n\r\n",
+            connection
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        stream
+    }
+
+    // serves one connection at most with `overflow`, while `client` runs
+    // on another thread, and hands back what it returned
+    fn serve_limited<F>(overflow: Overflow, client: F) -> Vec<String>
+    where
+        F: FnOnce(SocketAddr) -> Vec<String> + Send + 'static,
+    {
+        let config = ServerConfig {
+            max_connections: Some(1),
+            overflow,
+            shutdown_deadline: Duration::from_millis(100),
+            reuse_port: false,
+        };
+        let server = Server::bind("127.0.0.1:0", config).unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut router = Router::new();
+        router.insert(Method::GET, "/", || async { "ok" });
+
+        let (done, finished) = mpsc::channel();
+        let client = thread::spawn(move || {
+            let responses = client(addr);
+            done.send(()).unwrap();
+            responses
+        });
+        let handle = server.shutdown().handle();
+        executor::block_on(async move {
+            executor::spawn(async move {
+                while let Err(mpsc::TryRecvError::Empty) = finished.try_recv() {
+                    sleep(Duration::from_millis(10)).await;
+                }
+                handle.shutdown();
+            });
+            server.serve(router).await;
+        })
+        .unwrap();
+        client.join().expect("client failed")
+    }
+
+    #[test]
+    fn test_reject_over_limit() {
+        let responses = serve_limited(Overflow::Reject, |addr| {
+            let mut first = get(addr, "keep-alive");
+            let kept = read_response(&mut first);
+            // answered before it's read, closing on an unread request
+            // would reset the connection
+            let rejected = read_response(&mut TcpStream::connect(addr).unwrap());
+            drop(first);
+            thread::sleep(Duration::from_millis(100));
+            let after = read_response(&mut get(addr, "close"));
+            vec![kept, rejected, after]
+        });
+        assert!(responses[0].starts_with("HTTP/1.1 200"));
+        assert!(responses[1].starts_with("HTTP/1.1 503"));
+        assert!(responses[2].starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn test_pause_over_limit() {
+        let responses = serve_limited(Overflow::Pause, |addr| {
+            let mut first = get(addr, "keep-alive");
+            let kept = read_response(&mut first);
+            // sits in the backlog until the first connection goes away
+            let mut second = get(addr, "close");
+            let waiting = read_response(&mut second);
+            drop(first);
+            let served = read_response(&mut second);
+            vec![kept, waiting, served]
+        });
+        assert!(responses[0].starts_with("HTTP/1.1 200"));
+        assert_eq!(responses[1], "");
+        assert!(responses[2].starts_with("HTTP/1.1 200"));
+    }
+}
@@ -0,0 +1,64 @@
+//! Fixtures shared by the tests of the `web` modules.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::io::Write;
+use std::net;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::async_io::executor;
+use crate::async_net::client::TcpClient;
+
+use super::body::{Body, BodyKind};
+use super::connection::Connection;
+use super::headers::Headers;
+use super::request::{Method, Request, Version};
+use super::response::Response;
+use super::router::Router;
+
+/// A connection with `data` waiting to be read off of it.
+pub fn connection(data: &[u8]) -> Connection {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut peer = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    peer.write_all(data).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    Connection::new(TcpClient::new(stream))
+}
+
+/// An HTTP/1.1 request for `target`, with `body` waiting to be read off a
+/// socket unless it's empty.
+pub fn request(method: Method, target: &str, headers: &[(&str, &str)], body: &[u8]) -> Request {
+    let mut fields = Headers::new();
+    for (name, value) in headers {
+        fields.append(name, value);
+    }
+    let kind = match body.len() {
+        0 => BodyKind::Empty,
+        len => BodyKind::Length(len as u64),
+    };
+    let mut req = Request::new(method, String::from(target), Version::Http11, fields, kind);
+    if !body.is_empty() {
+        req.set_body(Body::new(kind, connection(body), Duration::from_secs(1)));
+    }
+    req
+}
+
+/// Runs `future` on this thread's executor and hands back its output.
+pub fn block_on<T: 'static>(future: impl Future<Output = T> + 'static) -> T {
+    let output = Rc::new(RefCell::new(None));
+    let out = output.clone();
+    executor::block_on(async move {
+        *out.borrow_mut() = Some(future.await);
+    })
+    .unwrap();
+    let output = output.borrow_mut().take();
+    output.expect("the future never finished")
+}
+
+/// The response `router` gives `req`.
+pub fn run(router: &Rc<Router>, req: Request) -> Response {
+    let router = router.clone();
+    block_on(async move { router.handle(req).await })
+}
@@ -0,0 +1,469 @@
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+use crate::async_io::task_queue::LocalBoxedFuture;
+use crate::async_io::timer::timeout;
+
+use super::connection::Connection;
+use super::extract::FromRequestParts;
+use super::into_response::IntoResponse;
+use super::request::{Method, Request, Version};
+use super::response::{Response, ResponseBody};
+
+// appended to the client's key to prove the server understood the handshake
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// close codes for a peer that broke the protocol
+const NORMAL_CLOSURE: u16 = 1000;
+const PROTOCOL_ERROR: u16 = 1002;
+const INVALID_DATA: u16 = 1007;
+const MESSAGE_TOO_BIG: u16 = 1009;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// The `Sec-WebSocket-Accept` value answering a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(GUID.as_bytes());
+    STANDARD.encode(hasher.finalize())
+}
+
+/// A valid opening handshake, waiting for the handler to accept it.
+pub struct WebSocketUpgrade {
+    accept: String,
+    max_message_size: usize,
+    idle_timeout: Duration,
+}
+
+impl WebSocketUpgrade {
+    /// Checks that `req` asks for a WebSocket, failing with the response to
+    /// send instead: a 400, or a 426 naming the version this server speaks.
+    pub fn from_request(req: &Request) -> Result<Self, Response> {
+        let bad_request = |reason: &str| (400, format!("{}\n", reason)).into_response();
+        if req.method != Method::GET || req.version != Version::Http11 {
+            return Err(bad_request("websocket handshake must be an HTTP/1.1 GET"));
+        }
+        if !req.headers.has_token("connection", "upgrade")
+            || !req.headers.has_token("upgrade", "websocket")
+        {
+            return Err(bad_request("missing websocket upgrade"));
+        }
+        if req.header("sec-websocket-version").map(str::trim) != Some("13") {
+            return Err(Response::builder()
+                .status(426)
+                .header("sec-websocket-version", "13")
+                .content_type("text/plain; charset=UTF-8")
+                .body("unsupported websocket version\n"));
+        }
+        let key = req.header("sec-websocket-key").unwrap_or_default().trim();
+        match STANDARD.decode(key) {
+            Ok(nonce) if nonce.len() == 16 => {}
+            _ => return Err(bad_request("invalid sec-websocket-key")),
+        }
+
+        Ok(WebSocketUpgrade {
+            accept: accept_key(key),
+            max_message_size: 64 * 1024,
+            idle_timeout: Duration::from_secs(60),
+        })
+    }
+
+    /// The largest message, all fragments together, the peer may send.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// How long `recv` waits on a peer that sends nothing at all, not even
+    /// a ping, before failing with `TimedOut`.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// The `101` response that switches protocols, after which the router
+    /// hands the connection to `f` as a `WebSocket`.
+    pub fn on_upgrade<F, Fut>(self, f: F) -> Response
+    where
+        F: FnOnce(WebSocket) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let max_message_size = self.max_message_size;
+        let idle_timeout = self.idle_timeout;
+        Response::builder()
+            .status(101)
+            .header("upgrade", "websocket")
+            .header("connection", "upgrade")
+            .header("sec-websocket-accept", &self.accept)
+            .body(ResponseBody::Upgrade(Box::new(move |conn| {
+                Box::pin(f(WebSocket::new(conn, max_message_size, idle_timeout)))
+            })))
+    }
+}
+
+impl FromRequestParts for WebSocketUpgrade {
+    type Rejection = Response;
+
+    fn from_request_parts(req: &Request) -> LocalBoxedFuture<'_, Result<Self, Response>> {
+        let upgrade = Self::from_request(req);
+        Box::pin(async move { upgrade })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // the status code and reason, if the peer gave one
+    Close(Option<(u16, String)>),
+}
+
+impl Message {
+    /// A close with the normal status code.
+    pub fn close() -> Self {
+        Message::Close(Some((NORMAL_CLOSURE, String::new())))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// A WebSocket connection on the server's side.
+pub struct WebSocket {
+    conn: Connection,
+    max_message_size: usize,
+    idle_timeout: Duration,
+    // the opcode and data so far of a fragmented message
+    partial: Option<(u8, Vec<u8>)>,
+    sent_close: bool,
+    received_close: bool,
+}
+
+impl WebSocket {
+    fn new(conn: Connection, max_message_size: usize, idle_timeout: Duration) -> Self {
+        WebSocket {
+            conn,
+            max_message_size,
+            idle_timeout,
+            partial: None,
+            sent_close: false,
+            received_close: false,
+        }
+    }
+
+    /// The next message from the peer, or `None` once the connection is
+    /// closed. Pings are answered as they come in, and a close is echoed
+    /// back before it's returned.
+    pub async fn recv(&mut self) -> io::Result<Option<Message>> {
+        while !self.received_close {
+            let message = match self.read_frame().await? {
+                Some(frame) => frame.and_then(|frame| self.assemble(frame)),
+                // the peer went away without closing
+                None => {
+                    self.received_close = true;
+                    break;
+                }
+            };
+            match message {
+                // a fragment of a message still coming in
+                Ok(None) => continue,
+                Ok(Some(Message::Ping(data))) => {
+                    self.send_frame(OP_PONG, &data).await?;
+                    return Ok(Some(Message::Ping(data)));
+                }
+                Ok(Some(Message::Close(close))) => {
+                    self.received_close = true;
+                    if !self.sent_close {
+                        let code = close.as_ref().map(|(code, _)| *code);
+                        self.send_close(code, "").await?;
+                    }
+                    return Ok(Some(Message::Close(close)));
+                }
+                Ok(message) => return Ok(message),
+                Err(code) => {
+                    self.received_close = true;
+                    if !self.sent_close {
+                        self.send_close(Some(code), "").await?;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub async fn send(&mut self, message: Message) -> io::Result<()> {
+        if self.sent_close {
+            return Err(io::Error::new(
+                ErrorKind::NotConnected,
+                "websocket is closing",
+            ));
+        }
+        match message {
+            Message::Text(text) => self.send_frame(OP_TEXT, text.as_bytes()).await,
+            Message::Binary(data) => self.send_frame(OP_BINARY, &data).await,
+            Message::Ping(data) => self.send_frame(OP_PING, &data).await,
+            Message::Pong(data) => self.send_frame(OP_PONG, &data).await,
+            Message::Close(close) => match close {
+                Some((code, reason)) => self.send_close(Some(code), &reason).await,
+                None => self.send_close(None, "").await,
+            },
+        }
+    }
+
+    /// Starts the close handshake and waits a little for the peer to
+    /// answer it, discarding whatever else it sends in the meantime.
+    pub async fn close(mut self, code: u16, reason: &str) -> io::Result<()> {
+        if !self.sent_close {
+            self.send_close(Some(code), reason).await?;
+        }
+        let drain = async {
+            while self.recv().await?.is_some() {}
+            Ok::<_, io::Error>(())
+        };
+        timeout(Duration::from_secs(5), drain).await?
+    }
+
+    async fn read_frame(&mut self) -> io::Result<Option<Result<Frame, u16>>> {
+        loop {
+            match parse_frame(self.conn.buffered(), self.max_message_size) {
+                Ok(Some((frame, len))) => {
+                    self.conn.consume(len);
+                    return Ok(Some(Ok(frame)));
+                }
+                Ok(None) => {
+                    if timeout(self.idle_timeout, self.conn.fill()).await?? == 0 {
+                        return Ok(None);
+                    }
+                }
+                Err(code) => return Ok(Some(Err(code))),
+            }
+        }
+    }
+
+    // puts fragments back together, yielding complete messages
+    fn assemble(&mut self, frame: Frame) -> Result<Option<Message>, u16> {
+        let (opcode, data) = match frame.opcode {
+            OP_PING => return Ok(Some(Message::Ping(frame.payload))),
+            OP_PONG => return Ok(Some(Message::Pong(frame.payload))),
+            OP_CLOSE => {
+                return parse_close(&frame.payload).map(|close| Some(Message::Close(close)))
+            }
+            OP_CONTINUATION => {
+                let (opcode, mut data) = self.partial.take().ok_or(PROTOCOL_ERROR)?;
+                if data.len() + frame.payload.len() > self.max_message_size {
+                    return Err(MESSAGE_TOO_BIG);
+                }
+                data.extend_from_slice(&frame.payload);
+                (opcode, data)
+            }
+            // a new message can't start in the middle of another
+            _ if self.partial.is_some() => return Err(PROTOCOL_ERROR),
+            opcode => (opcode, frame.payload),
+        };
+
+        if !frame.fin {
+            self.partial = Some((opcode, data));
+            return Ok(None);
+        }
+        match opcode {
+            OP_TEXT => match String::from_utf8(data) {
+                Ok(text) => Ok(Some(Message::Text(text))),
+                Err(_) => Err(INVALID_DATA),
+            },
+            _ => Ok(Some(Message::Binary(data))),
+        }
+    }
+
+    async fn send_close(&mut self, code: Option<u16>, reason: &str) -> io::Result<()> {
+        self.sent_close = true;
+        let mut payload = Vec::new();
+        if let Some(code) = code {
+            payload.extend_from_slice(&code.to_be_bytes());
+            payload.extend_from_slice(reason.as_bytes());
+        }
+        self.send_frame(OP_CLOSE, &payload).await
+    }
+
+    async fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let frame = encode_frame(opcode, payload);
+        let client = self.conn.client_mut();
+        client.write_all(&frame).await?;
+        client.flush();
+        Ok(())
+    }
+}
+
+/// Parses one frame off the front of `buf`, `Ok(None)` if it isn't all
+/// there yet. A frame that breaks the protocol fails with the close code
+/// to answer it with.
+fn parse_frame(buf: &[u8], max_payload: usize) -> Result<Option<(Frame, usize)>, u16> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buf[0] & 0x80 != 0;
+    let opcode = buf[0] & 0x0f;
+    // no extensions are negotiated, so the reserved bits have to be clear
+    if buf[0] & 0x70 != 0 {
+        return Err(PROTOCOL_ERROR);
+    }
+    if !matches!(
+        opcode,
+        OP_CONTINUATION | OP_TEXT | OP_BINARY | OP_CLOSE | OP_PING | OP_PONG
+    ) {
+        return Err(PROTOCOL_ERROR);
+    }
+    // everything a client sends has to be masked
+    if buf[1] & 0x80 == 0 {
+        return Err(PROTOCOL_ERROR);
+    }
+
+    let (len, at) = match buf[1] & 0x7f {
+        126 if buf.len() < 4 => return Ok(None),
+        126 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+        127 if buf.len() < 10 => return Ok(None),
+        127 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+        len => (len as u64, 2),
+    };
+    let control = opcode & 0x8 != 0;
+    if control && (!fin || len > 125) {
+        return Err(PROTOCOL_ERROR);
+    }
+    if len > max_payload as u64 {
+        return Err(MESSAGE_TOO_BIG);
+    }
+
+    let len = len as usize;
+    if buf.len() < at + 4 + len {
+        return Ok(None);
+    }
+    let mask = &buf[at..at + 4];
+    let payload = buf[at + 4..at + 4 + len]
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ mask[i % 4])
+        .collect();
+    Ok(Some((
+        Frame {
+            fin,
+            opcode,
+            payload,
+        },
+        at + 4 + len,
+    )))
+}
+
+// frames from the server are never masked or fragmented
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<(u16, String)>, u16> {
+    let (code, reason) = match payload {
+        [] => return Ok(None),
+        [_] => return Err(PROTOCOL_ERROR),
+        [high, low, reason @ ..] => (u16::from_be_bytes([*high, *low]), reason),
+    };
+    // 1005 and 1006 only stand in for a missing code locally, 1004 is reserved
+    let valid = matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999);
+    if !valid {
+        return Err(PROTOCOL_ERROR);
+    }
+    let reason = String::from_utf8(reason.to_vec()).map_err(|_| INVALID_DATA)?;
+    Ok(Some((code, reason)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masked(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![first, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // the example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        let frame = masked(0x81, b"Hello");
+        let (parsed, len) = parse_frame(&frame, 1024).unwrap().unwrap();
+        assert_eq!(len, frame.len());
+        assert_eq!(
+            parsed,
+            Frame {
+                fin: true,
+                opcode: OP_TEXT,
+                payload: b"Hello".to_vec()
+            }
+        );
+        assert_eq!(parse_frame(&frame[..4], 1024), Ok(None));
+
+        // unmasked, reserved bits, unknown opcode, fragmented ping
+        assert_eq!(parse_frame(&[0x81, 0x00], 1024), Err(PROTOCOL_ERROR));
+        assert_eq!(parse_frame(&masked(0xc1, b""), 1024), Err(PROTOCOL_ERROR));
+        assert_eq!(parse_frame(&masked(0x83, b""), 1024), Err(PROTOCOL_ERROR));
+        assert_eq!(parse_frame(&masked(0x09, b""), 1024), Err(PROTOCOL_ERROR));
+        assert_eq!(
+            parse_frame(&masked(0x82, b"12345"), 4),
+            Err(MESSAGE_TOO_BIG)
+        );
+
+        assert_eq!(encode_frame(OP_TEXT, b"Hi"), [0x81, 0x02, b'H', b'i']);
+        assert_eq!(
+            &encode_frame(OP_BINARY, &[0; 300])[..4],
+            [0x82, 126, 0x01, 0x2c]
+        );
+    }
+
+    #[test]
+    fn test_parse_close() {
+        assert_eq!(parse_close(&[]), Ok(None));
+        assert_eq!(parse_close(&[0x03]), Err(PROTOCOL_ERROR));
+        assert_eq!(
+            parse_close(&[0x03, 0xe8, b'b', b'y', b'e']),
+            Ok(Some((1000, String::from("bye"))))
+        );
+        assert_eq!(parse_close(&[0x03, 0xed]), Err(PROTOCOL_ERROR));
+        assert_eq!(parse_close(&[0x03, 0xe8, 0xff]), Err(INVALID_DATA));
+    }
+}